@@ -0,0 +1,220 @@
+use color_eyre::eyre::{OptionExt, Result};
+use poise::CreateReply;
+use serenity::all::{
+    ChannelId, Mentionable, PermissionOverwrite, PermissionOverwriteType, Permissions, User,
+};
+
+use crate::data::{config::CollaboratorPosts, Context};
+
+super::command! {
+    false, subcommands("add", "remove", "list"), subcommand_required;
+    /// Manage who else may post in your monologue channel
+    pub async fn collab(ctx: Context<'_>) -> Result<()> {
+        // unreachable because a subcommand is required
+        Ok(())
+    }
+}
+
+/// Get the monologue channel of the user running the command, telling them if
+/// they don't have one
+async fn own_channel(ctx: &Context<'_>) -> Result<Option<ChannelId>> {
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+
+    let channel_id = ctx
+        .data()
+        .state
+        .lock()
+        .await
+        .get_channel(guild_id, ctx.author().id);
+
+    if channel_id.is_none() {
+        ctx.send(
+            CreateReply::default()
+                .content("You don't have a monologue channel, create one with `/create`")
+                .ephemeral(true),
+        )
+        .await?;
+    }
+
+    Ok(channel_id)
+}
+
+/// Let someone post in your monologue channel
+#[poise::command(slash_command, guild_only)]
+async fn add(
+    ctx: Context<'_>,
+    #[description = "The user to add as a collaborator"] user: User,
+) -> Result<()> {
+    crate::command::__trace_cmd!(ctx ctx, "collab add");
+
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+
+    let Some(channel_id) = own_channel(&ctx).await? else {
+        return Ok(());
+    };
+
+    let refusal = if user.id == ctx.author().id {
+        Some("You can always post in your own channel".to_string())
+    } else if user.bot {
+        Some("Bots can't be collaborators".to_string())
+    } else if ctx
+        .data()
+        .state
+        .lock()
+        .await
+        .is_collaborator(guild_id, channel_id, user.id)
+    {
+        Some(format!("{} is already a collaborator", user.mention()))
+    } else {
+        None
+    };
+
+    if let Some(refusal) = refusal {
+        ctx.send(CreateReply::default().content(refusal).ephemeral(true))
+            .await?;
+
+        return Ok(());
+    }
+
+    // the overwrite is written even if anyone may post, so the collaborator
+    // keeps access if `allow_anyone` is turned off later
+    trace!("allowing collaborator to send messages");
+
+    let permission = PermissionOverwrite {
+        allow: Permissions::SEND_MESSAGES,
+        deny: Permissions::empty(),
+        kind: PermissionOverwriteType::Member(user.id),
+    };
+
+    channel_id.create_permission(ctx.http(), permission).await?;
+
+    ctx.data()
+        .state
+        .lock()
+        .await
+        .set_collaborator(guild_id, channel_id, user.id, true)
+        .await?;
+
+    ctx.send(
+        CreateReply::default()
+            .content(format!(
+                "{} can now post in {}",
+                user.mention(),
+                channel_id.mention()
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Stop someone from posting in your monologue channel
+#[poise::command(slash_command, guild_only)]
+async fn remove(
+    ctx: Context<'_>,
+    #[description = "The collaborator to remove"] user: User,
+) -> Result<()> {
+    crate::command::__trace_cmd!(ctx ctx, "collab remove");
+
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+
+    let Some(channel_id) = own_channel(&ctx).await? else {
+        return Ok(());
+    };
+
+    if !ctx
+        .data()
+        .state
+        .lock()
+        .await
+        .is_collaborator(guild_id, channel_id, user.id)
+    {
+        ctx.send(
+            CreateReply::default()
+                .content(format!("{} is not a collaborator", user.mention()))
+                .ephemeral(true),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    trace!("removing collaborator permissions");
+
+    channel_id
+        .delete_permission(ctx.http(), PermissionOverwriteType::Member(user.id))
+        .await?;
+
+    ctx.data()
+        .state
+        .lock()
+        .await
+        .set_collaborator(guild_id, channel_id, user.id, false)
+        .await?;
+
+    ctx.send(
+        CreateReply::default()
+            .content(format!(
+                "{} can no longer post in {}",
+                user.mention(),
+                channel_id.mention()
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// List the collaborators of your monologue channel
+#[poise::command(slash_command, guild_only)]
+async fn list(ctx: Context<'_>) -> Result<()> {
+    crate::command::__trace_cmd!(ctx ctx, "collab list");
+
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+
+    let Some(channel_id) = own_channel(&ctx).await? else {
+        return Ok(());
+    };
+
+    let collaborators = ctx
+        .data()
+        .state
+        .lock()
+        .await
+        .collaborators(guild_id, channel_id);
+
+    let content = if collaborators.is_empty() {
+        "Your channel has no collaborators, add one with `/collab add`".to_string()
+    } else {
+        let mut collaborators = collaborators
+            .into_iter()
+            .map(|user_id| user_id.mention().to_string())
+            .collect::<Vec<_>>();
+        collaborators.sort_unstable();
+
+        let pool = match ctx
+            .data()
+            .guild_config(guild_id)
+            .await
+            .monologues
+            .collaborator_posts
+        {
+            CollaboratorPosts::Owner => "Their posts count as yours when drawing messages",
+            CollaboratorPosts::Author => "Their posts count as their own when drawing messages",
+        };
+
+        format!(
+            "Collaborators of {}: {}\n{}",
+            channel_id.mention(),
+            collaborators.join(", "),
+            pool
+        )
+    };
+
+    ctx.send(CreateReply::default().content(content).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,853 @@
+use std::{fmt::Display, str::FromStr};
+
+use color_eyre::eyre::{bail, OptionExt, Result, WrapErr};
+use poise::{ChoiceParameter, CreateReply};
+use regex::Regex;
+use serenity::all::{
+    ChannelId, GuildId, Mentionable, PermissionOverwrite, PermissionOverwriteType, Permissions,
+    RoleId,
+};
+
+use crate::{
+    data::{
+        config::{AppConfig, DrawMessage, RandomDrawConfig, RawDrawMessage, DEFAULT_DRAW},
+        settings::{GuildSettings, RandomDrawSettings},
+        Context,
+    },
+    utils,
+};
+
+super::command! {
+    true, subcommands("get", "set", "reset"), subcommand_required;
+    /// View or change the configuration for this server
+    ///
+    /// **Admin only**
+    pub async fn config(ctx: Context<'_>) -> Result<()> {
+        // unreachable because a subcommand is required
+        Ok(())
+    }
+}
+
+/// A configuration option that can be overridden per guild.
+///
+/// Options are chosen with autocomplete rather than as choices, because
+/// Discord only allows 25 choices per argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ChoiceParameter)]
+pub enum Key {
+    #[name = "monologues.category_id"]
+    CategoryId,
+    #[name = "monologues.allow_anyone"]
+    AllowAnyone,
+    #[name = "monologues.disable_sorting"]
+    DisableSorting,
+    #[name = "monologues.enforcement"]
+    Enforcement,
+    #[name = "monologues.guest_role_id"]
+    GuestRoleId,
+    #[name = "monologues.allow_replies"]
+    AllowReplies,
+    #[name = "monologues.notify_foreign"]
+    NotifyForeign,
+    #[name = "monologues.collaborator_posts"]
+    CollaboratorPosts,
+    #[name = "random_draw.enabled"]
+    DrawEnabled,
+    #[name = "random_draw.channel_id"]
+    DrawChannelId,
+    #[name = "random_draw.schedule"]
+    DrawSchedule,
+    #[name = "random_draw.timezone"]
+    DrawTimezone,
+    #[name = "random_draw.messages"]
+    DrawMessages,
+    #[name = "random_draw.strategy"]
+    DrawStrategy,
+    #[name = "random_draw.half_life_days"]
+    DrawHalfLifeDays,
+    #[name = "random_draw.cooldown_draws"]
+    DrawCooldownDraws,
+    #[name = "random_draw.cooldown_hours"]
+    DrawCooldownHours,
+    #[name = "random_draw.cooldown_authors"]
+    DrawCooldownAuthors,
+    #[name = "random_draw.fallback"]
+    DrawFallback,
+    #[name = "random_draw.fallback_message"]
+    DrawFallbackMessage,
+    #[name = "random_draw.min_age_days"]
+    DrawMinAgeDays,
+    #[name = "random_draw.max_age_days"]
+    DrawMaxAgeDays,
+    #[name = "random_draw.source_channels"]
+    DrawSourceChannels,
+    #[name = "random_draw.catch_up"]
+    DrawCatchUp,
+    #[name = "random_draw.on_this_day"]
+    DrawOnThisDay,
+    #[name = "random_draw.format"]
+    DrawFormat,
+    #[name = "random_draw.reupload_attachments"]
+    DrawReuploadAttachments,
+    #[name = "random_draw.reupload_limit_mb"]
+    DrawReuploadLimitMb,
+    #[name = "filters.min_length"]
+    FilterMinLength,
+    #[name = "filters.attachments"]
+    FilterAttachments,
+    #[name = "filters.exclude_links_only"]
+    FilterExcludeLinksOnly,
+    #[name = "filters.exclude_spoilers"]
+    FilterExcludeSpoilers,
+    #[name = "filters.exclude_polls"]
+    FilterExcludePolls,
+    #[name = "filters.exclude_patterns"]
+    FilterExcludePatterns,
+    #[name = "filters.max_age_days"]
+    FilterMaxAgeDays,
+}
+
+impl Key {
+    const ALL: [Key; 35] = [
+        Key::CategoryId,
+        Key::AllowAnyone,
+        Key::DisableSorting,
+        Key::Enforcement,
+        Key::GuestRoleId,
+        Key::AllowReplies,
+        Key::NotifyForeign,
+        Key::CollaboratorPosts,
+        Key::DrawEnabled,
+        Key::DrawChannelId,
+        Key::DrawSchedule,
+        Key::DrawTimezone,
+        Key::DrawMessages,
+        Key::DrawStrategy,
+        Key::DrawHalfLifeDays,
+        Key::DrawCooldownDraws,
+        Key::DrawCooldownHours,
+        Key::DrawCooldownAuthors,
+        Key::DrawFallback,
+        Key::DrawFallbackMessage,
+        Key::DrawMinAgeDays,
+        Key::DrawMaxAgeDays,
+        Key::DrawSourceChannels,
+        Key::DrawCatchUp,
+        Key::DrawOnThisDay,
+        Key::DrawFormat,
+        Key::DrawReuploadAttachments,
+        Key::DrawReuploadLimitMb,
+        Key::FilterMinLength,
+        Key::FilterAttachments,
+        Key::FilterExcludeLinksOnly,
+        Key::FilterExcludeSpoilers,
+        Key::FilterExcludePolls,
+        Key::FilterExcludePatterns,
+        Key::FilterMaxAgeDays,
+    ];
+
+    /// Whether this option belongs to a random draw rather than the guild
+    fn is_draw_key(self) -> bool {
+        !matches!(
+            self,
+            Key::CategoryId
+                | Key::AllowAnyone
+                | Key::DisableSorting
+                | Key::Enforcement
+                | Key::GuestRoleId
+                | Key::AllowReplies
+                | Key::NotifyForeign
+                | Key::CollaboratorPosts
+                | Key::FilterMinLength
+                | Key::FilterAttachments
+                | Key::FilterExcludeLinksOnly
+                | Key::FilterExcludeSpoilers
+                | Key::FilterExcludePolls
+                | Key::FilterExcludePatterns
+                | Key::FilterMaxAgeDays
+        )
+    }
+
+    /// Describe the current value of this option for the named random draw.
+    /// Returns the value and whether or not it is overridden by the guild
+    /// settings.
+    fn describe(
+        self,
+        config: &AppConfig,
+        settings: &GuildSettings,
+        draw_name: &str,
+    ) -> (String, bool) {
+        fn pick<T>(value: Option<T>, default: Option<T>, f: impl Fn(T) -> String) -> (String, bool) {
+            let overridden = value.is_some();
+
+            let value = value
+                .or(default)
+                .map(f)
+                .unwrap_or_else(|| "*unset*".to_string());
+
+            (value, overridden)
+        }
+
+        let draw = settings.draw(draw_name).cloned().unwrap_or_default();
+        let draw_default = config.draw(draw_name);
+        let filters = &settings.filters;
+        let filters_default = &config.filters;
+
+        match self {
+            Key::CategoryId => pick(settings.category_id, config.monologues.category_id, |c| {
+                c.mention().to_string()
+            }),
+            Key::AllowAnyone => pick(
+                settings.allow_anyone,
+                Some(config.monologues.allow_anyone),
+                |b| b.to_string(),
+            ),
+            Key::DisableSorting => pick(
+                settings.disable_sorting,
+                Some(config.monologues.disable_sorting),
+                |b| b.to_string(),
+            ),
+            Key::Enforcement => pick(
+                settings.enforcement,
+                Some(config.monologues.enforcement),
+                code,
+            ),
+            Key::GuestRoleId => pick(
+                settings.guest_role_id,
+                config.monologues.guest_role_id,
+                |r| r.mention().to_string(),
+            ),
+            Key::AllowReplies => pick(
+                settings.allow_replies,
+                Some(config.monologues.allow_replies),
+                |b| b.to_string(),
+            ),
+            Key::NotifyForeign => pick(
+                settings.notify_foreign,
+                Some(config.monologues.notify_foreign),
+                |b| b.to_string(),
+            ),
+            Key::CollaboratorPosts => pick(
+                settings.collaborator_posts,
+                Some(config.monologues.collaborator_posts),
+                code,
+            ),
+            Key::DrawEnabled => pick(draw.enabled, Some(true), |b| b.to_string()),
+            Key::DrawChannelId => pick(draw.channel_id, draw_default.map(|d| d.channel_id), |c| {
+                c.mention().to_string()
+            }),
+            Key::DrawSchedule => pick(
+                draw.schedule.as_ref(),
+                draw_default.map(|d| &d.schedule),
+                code,
+            ),
+            Key::DrawTimezone => pick(draw.timezone, draw_default.map(|d| d.timezone), code),
+            Key::DrawMessages => pick(
+                draw.messages.clone(),
+                draw_default.map(|d| {
+                    d.messages
+                        .iter()
+                        .cloned()
+                        .map(RawDrawMessage::from)
+                        .collect()
+                }),
+                |m| code(serde_json::to_string(&m).unwrap_or_default()),
+            ),
+            Key::DrawStrategy => pick(
+                draw.strategy,
+                Some(draw_default.map(|d| d.strategy).unwrap_or_default()),
+                code,
+            ),
+            Key::DrawHalfLifeDays => pick(
+                draw.half_life_days,
+                Some(
+                    draw_default
+                        .map(|d| d.half_life_days)
+                        .unwrap_or_else(RandomDrawConfig::default_half_life_days),
+                ),
+                code,
+            ),
+            Key::DrawCooldownDraws => pick(
+                draw.cooldown_draws,
+                Some(draw_default.map(|d| d.cooldown_draws).unwrap_or_default()),
+                |n| n.to_string(),
+            ),
+            Key::DrawCooldownHours => pick(
+                draw.cooldown_hours,
+                Some(draw_default.map(|d| d.cooldown_hours).unwrap_or_default()),
+                |n| n.to_string(),
+            ),
+            Key::DrawCooldownAuthors => pick(
+                draw.cooldown_authors,
+                Some(draw_default.is_some_and(|d| d.cooldown_authors)),
+                |b| b.to_string(),
+            ),
+            Key::DrawFallback => pick(
+                draw.fallback,
+                Some(draw_default.map(|d| d.fallback).unwrap_or_default()),
+                code,
+            ),
+            Key::DrawFallbackMessage => pick(
+                draw.fallback_message.clone(),
+                Some(
+                    draw_default
+                        .map(|d| d.fallback_message.clone())
+                        .unwrap_or_else(RandomDrawConfig::default_fallback_message),
+                ),
+                code,
+            ),
+            Key::DrawMinAgeDays => pick(
+                draw.min_age_days,
+                draw_default.and_then(|d| d.min_age_days),
+                |n| n.to_string(),
+            ),
+            Key::DrawMaxAgeDays => pick(
+                draw.max_age_days,
+                draw_default.and_then(|d| d.max_age_days),
+                |n| n.to_string(),
+            ),
+            Key::DrawSourceChannels => pick(
+                draw.source_channels.clone(),
+                Some(draw_default.map(|d| d.source_channels.clone()).unwrap_or_default()),
+                |channels| {
+                    if channels.is_empty() {
+                        "every channel".to_string()
+                    } else {
+                        channels
+                            .iter()
+                            .map(|c| c.mention().to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    }
+                },
+            ),
+            Key::DrawCatchUp => pick(
+                draw.catch_up,
+                Some(draw_default.map(|d| d.catch_up).unwrap_or_default()),
+                code,
+            ),
+            Key::DrawOnThisDay => pick(
+                draw.on_this_day,
+                Some(draw_default.is_some_and(|d| d.on_this_day)),
+                |b| b.to_string(),
+            ),
+            Key::DrawFormat => pick(
+                draw.format,
+                Some(draw_default.map(|d| d.format).unwrap_or_default()),
+                code,
+            ),
+            Key::DrawReuploadAttachments => pick(
+                draw.reupload_attachments,
+                Some(draw_default.is_some_and(|d| d.reupload_attachments)),
+                |b| b.to_string(),
+            ),
+            Key::DrawReuploadLimitMb => pick(
+                draw.reupload_limit_mb,
+                Some(
+                    draw_default
+                        .map(|d| d.reupload_limit_mb)
+                        .unwrap_or_else(RandomDrawConfig::default_reupload_limit_mb),
+                ),
+                |n| n.to_string(),
+            ),
+            Key::FilterMinLength => pick(
+                filters.min_length,
+                Some(filters_default.min_length),
+                |n| n.to_string(),
+            ),
+            Key::FilterAttachments => pick(
+                filters.attachments,
+                Some(filters_default.attachments),
+                code,
+            ),
+            Key::FilterExcludeLinksOnly => pick(
+                filters.exclude_links_only,
+                Some(filters_default.exclude_links_only),
+                |b| b.to_string(),
+            ),
+            Key::FilterExcludeSpoilers => pick(
+                filters.exclude_spoilers,
+                Some(filters_default.exclude_spoilers),
+                |b| b.to_string(),
+            ),
+            Key::FilterExcludePolls => pick(
+                filters.exclude_polls,
+                Some(filters_default.exclude_polls),
+                |b| b.to_string(),
+            ),
+            Key::FilterExcludePatterns => pick(
+                filters.exclude_patterns.clone(),
+                Some(
+                    filters_default
+                        .exclude_patterns
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect(),
+                ),
+                |p| code(serde_json::to_string(&p).unwrap_or_default()),
+            ),
+            Key::FilterMaxAgeDays => pick(
+                filters.max_age_days,
+                filters_default.max_age_days,
+                |n| n.to_string(),
+            ),
+        }
+    }
+
+    /// Parse `value` and store it as the override for this option
+    fn set(self, settings: &mut GuildSettings, draw_name: &str, value: &str) -> Result<()> {
+        let mut draw = settings.draw(draw_name).cloned().unwrap_or_default();
+
+        match self {
+            Key::CategoryId => settings.category_id = Some(parse_channel(value)?),
+            Key::AllowAnyone => settings.allow_anyone = Some(parse_bool(value)?),
+            Key::DisableSorting => settings.disable_sorting = Some(parse_bool(value)?),
+            Key::Enforcement => settings.enforcement = Some(value.parse()?),
+            Key::GuestRoleId => settings.guest_role_id = Some(parse_role(value)?),
+            Key::AllowReplies => settings.allow_replies = Some(parse_bool(value)?),
+            Key::NotifyForeign => settings.notify_foreign = Some(parse_bool(value)?),
+            Key::CollaboratorPosts => settings.collaborator_posts = Some(value.parse()?),
+            Key::DrawEnabled => draw.enabled = Some(parse_bool(value)?),
+            Key::DrawChannelId => draw.channel_id = Some(parse_channel(value)?),
+            Key::DrawSchedule => draw.schedule = Some(value.parse()?),
+            Key::DrawTimezone => draw.timezone = Some(value.parse()?),
+            Key::DrawMessages => {
+                let messages: Vec<RawDrawMessage> = serde_json::from_str(value).wrap_err(
+                    "messages must be a JSON array of templates or objects with a `template`",
+                )?;
+
+                // reject invalid messages now instead of skipping them later
+                for message in &messages {
+                    DrawMessage::try_from(message.clone())?;
+                }
+
+                draw.messages = Some(messages)
+            }
+            Key::DrawStrategy => draw.strategy = Some(value.parse()?),
+            Key::DrawHalfLifeDays => draw.half_life_days = Some(parse_half_life(value)?),
+            Key::DrawCooldownDraws => draw.cooldown_draws = Some(parse_count(value)?),
+            Key::DrawCooldownHours => draw.cooldown_hours = Some(parse_count(value)?),
+            Key::DrawCooldownAuthors => draw.cooldown_authors = Some(parse_bool(value)?),
+            Key::DrawFallback => draw.fallback = Some(value.parse()?),
+            Key::DrawFallbackMessage => draw.fallback_message = Some(value.to_string()),
+            Key::DrawMinAgeDays => draw.min_age_days = Some(parse_count(value)?),
+            Key::DrawMaxAgeDays => draw.max_age_days = Some(parse_count(value)?),
+            Key::DrawSourceChannels => draw.source_channels = Some(parse_channels(value)?),
+            Key::DrawCatchUp => draw.catch_up = Some(value.parse()?),
+            Key::DrawOnThisDay => draw.on_this_day = Some(parse_bool(value)?),
+            Key::DrawFormat => draw.format = Some(value.parse()?),
+            Key::DrawReuploadAttachments => {
+                draw.reupload_attachments = Some(parse_bool(value)?)
+            }
+            Key::DrawReuploadLimitMb => draw.reupload_limit_mb = Some(parse_count(value)?),
+            Key::FilterMinLength => settings.filters.min_length = Some(parse_count(value)?),
+            Key::FilterAttachments => settings.filters.attachments = Some(value.parse()?),
+            Key::FilterExcludeLinksOnly => {
+                settings.filters.exclude_links_only = Some(parse_bool(value)?)
+            }
+            Key::FilterExcludeSpoilers => {
+                settings.filters.exclude_spoilers = Some(parse_bool(value)?)
+            }
+            Key::FilterExcludePolls => settings.filters.exclude_polls = Some(parse_bool(value)?),
+            Key::FilterExcludePatterns => {
+                let patterns: Vec<String> = serde_json::from_str(value)
+                    .wrap_err("patterns must be a JSON array of regular expressions")?;
+
+                // reject invalid patterns now instead of skipping them later
+                for pattern in &patterns {
+                    Regex::new(pattern)
+                        .wrap_err_with(|| format!("invalid pattern `{}`", pattern))?;
+                }
+
+                settings.filters.exclude_patterns = Some(patterns)
+            }
+            Key::FilterMaxAgeDays => settings.filters.max_age_days = Some(parse_count(value)?),
+        }
+
+        if self.is_draw_key() {
+            *settings.draw_mut(draw_name) = draw;
+        }
+
+        Ok(())
+    }
+
+    /// Remove the override for this option
+    fn reset(self, settings: &mut GuildSettings, draw_name: &str) {
+        let mut draw = settings.draw(draw_name).cloned().unwrap_or_default();
+
+        match self {
+            Key::CategoryId => settings.category_id = None,
+            Key::AllowAnyone => settings.allow_anyone = None,
+            Key::DisableSorting => settings.disable_sorting = None,
+            Key::Enforcement => settings.enforcement = None,
+            Key::GuestRoleId => settings.guest_role_id = None,
+            Key::AllowReplies => settings.allow_replies = None,
+            Key::NotifyForeign => settings.notify_foreign = None,
+            Key::CollaboratorPosts => settings.collaborator_posts = None,
+            Key::DrawEnabled => draw.enabled = None,
+            Key::DrawChannelId => draw.channel_id = None,
+            Key::DrawSchedule => draw.schedule = None,
+            Key::DrawTimezone => draw.timezone = None,
+            Key::DrawMessages => draw.messages = None,
+            Key::DrawStrategy => draw.strategy = None,
+            Key::DrawHalfLifeDays => draw.half_life_days = None,
+            Key::DrawCooldownDraws => draw.cooldown_draws = None,
+            Key::DrawCooldownHours => draw.cooldown_hours = None,
+            Key::DrawCooldownAuthors => draw.cooldown_authors = None,
+            Key::DrawFallback => draw.fallback = None,
+            Key::DrawFallbackMessage => draw.fallback_message = None,
+            Key::DrawMinAgeDays => draw.min_age_days = None,
+            Key::DrawMaxAgeDays => draw.max_age_days = None,
+            Key::DrawSourceChannels => draw.source_channels = None,
+            Key::DrawCatchUp => draw.catch_up = None,
+            Key::DrawOnThisDay => draw.on_this_day = None,
+            Key::DrawFormat => draw.format = None,
+            Key::DrawReuploadAttachments => draw.reupload_attachments = None,
+            Key::DrawReuploadLimitMb => draw.reupload_limit_mb = None,
+            Key::FilterMinLength => settings.filters.min_length = None,
+            Key::FilterAttachments => settings.filters.attachments = None,
+            Key::FilterExcludeLinksOnly => settings.filters.exclude_links_only = None,
+            Key::FilterExcludeSpoilers => settings.filters.exclude_spoilers = None,
+            Key::FilterExcludePolls => settings.filters.exclude_polls = None,
+            Key::FilterExcludePatterns => settings.filters.exclude_patterns = None,
+            Key::FilterMaxAgeDays => settings.filters.max_age_days = None,
+        }
+
+        if self.is_draw_key() {
+            *settings.draw_mut(draw_name) = draw;
+        }
+    }
+}
+
+fn code(value: impl Display) -> String {
+    format!("`{}`", value)
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    value
+        .trim()
+        .parse()
+        .wrap_err("value must be `true` or `false`")
+}
+
+/// Parse a list of channel IDs or mentions separated by commas or spaces.
+/// `all` clears the list
+fn parse_channels(value: &str) -> Result<Vec<ChannelId>> {
+    if value.trim() == "all" {
+        return Ok(Vec::new());
+    }
+
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(parse_channel)
+        .collect()
+}
+
+fn parse_count<T: FromStr>(value: &str) -> Result<T> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| color_eyre::eyre::eyre!("value must be a whole number of at least zero"))
+}
+
+fn parse_half_life(value: &str) -> Result<f64> {
+    match value.trim().parse::<f64>() {
+        Ok(days) if days > 0.0 && days.is_finite() => Ok(days),
+        _ => Err(color_eyre::eyre::eyre!(
+            "value must be a positive number of days"
+        )),
+    }
+}
+
+/// Parse a channel ID from either a raw ID or a channel mention
+fn parse_channel(value: &str) -> Result<ChannelId> {
+    let value = value.trim();
+    let id = value
+        .strip_prefix("<#")
+        .and_then(|v| v.strip_suffix('>'))
+        .unwrap_or(value);
+
+    id.parse()
+        .map_err(|_| color_eyre::eyre::eyre!("value must be a channel ID or mention"))
+}
+
+/// Parse a role ID from either a raw ID or a role mention
+fn parse_role(value: &str) -> Result<RoleId> {
+    let value = value.trim();
+    let id = value
+        .strip_prefix("<@&")
+        .and_then(|v| v.strip_suffix('>'))
+        .unwrap_or(value);
+
+    id.parse()
+        .map_err(|_| color_eyre::eyre::eyre!("value must be a role ID or mention"))
+}
+
+/// Parse the name of a configuration option
+fn parse_key(name: &str) -> Result<Key> {
+    Key::from_name(name.trim())
+        .ok_or_else(|| color_eyre::eyre::eyre!("unknown option `{}`", name.trim()))
+}
+
+/// Suggest configuration options containing what was typed so far
+async fn autocomplete_key(_ctx: Context<'_>, partial: &str) -> Vec<String> {
+    Key::ALL
+        .iter()
+        .map(|key| key.name())
+        .filter(|name| name.contains(partial))
+        // Discord shows at most 25 suggestions
+        .take(25)
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Describe which random draw `key` applies to, if any
+fn draw_suffix(key: Key, draw_name: &str) -> String {
+    if key.is_draw_key() && draw_name != DEFAULT_DRAW {
+        format!(" of draw `{}`", draw_name)
+    } else {
+        String::new()
+    }
+}
+
+/// Check that a random draw name is usable, falling back to the default draw
+/// if none was given
+fn draw_name(draw: Option<String>) -> Result<String> {
+    let Some(name) = draw else {
+        return Ok(DEFAULT_DRAW.to_string());
+    };
+
+    let valid = !name.is_empty()
+        && name.len() <= 32
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if !valid {
+        bail!("draw names must be 1 to 32 letters, numbers, dashes or underscores");
+    }
+
+    Ok(name)
+}
+
+/// Show the configuration for this server
+#[poise::command(slash_command, guild_only)]
+async fn get(
+    ctx: Context<'_>,
+    #[description = "The option to show. Shows every option if omitted"]
+    #[autocomplete = "autocomplete_key"]
+    key: Option<String>,
+    #[description = "The random draw to show the options of. Defaults to the default draw"]
+    #[autocomplete = "super::trigger::autocomplete_draw"]
+    draw: Option<String>,
+) -> Result<()> {
+    crate::command::__trace_cmd!(ctx ctx, "config get");
+
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+    let key = key.as_deref().map(parse_key).transpose()?;
+    let draw_name = draw_name(draw)?;
+    let settings = ctx.data().state.lock().await.settings(guild_id);
+
+    let keys = match key {
+        Some(key) => vec![key],
+        None => Key::ALL.to_vec(),
+    };
+
+    let mut lines = keys
+        .into_iter()
+        .map(|key| {
+            let (value, overridden) = key.describe(&ctx.data().config, &settings, &draw_name);
+
+            format!(
+                "`{}`: {}{}",
+                key.name(),
+                value,
+                if overridden { "" } else { " (default)" }
+            )
+        })
+        .collect::<Vec<_>>();
+
+    if draw_name != DEFAULT_DRAW && key.is_none_or(Key::is_draw_key) {
+        lines.insert(0, format!("Random draw `{}`:", draw_name));
+    }
+
+    // every option together is longer than a single message allows
+    for content in utils::split_content(lines) {
+        ctx.send(CreateReply::default().content(content).ephemeral(true))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Override a configuration option for this server
+#[poise::command(slash_command, guild_only)]
+async fn set(
+    ctx: Context<'_>,
+    #[description = "The option to change"]
+    #[autocomplete = "autocomplete_key"]
+    key: String,
+    #[description = "The new value of the option"] value: String,
+    #[description = "The random draw to change. Defaults to the default draw, creates the draw if it doesn't exist"]
+    #[autocomplete = "super::trigger::autocomplete_draw"]
+    draw: Option<String>,
+) -> Result<()> {
+    crate::command::__trace_cmd!(ctx ctx, "config set");
+
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+    let key = parse_key(&key)?;
+    let draw_name = draw_name(draw)?;
+
+    let previous = ctx.data().guild_config(guild_id).await;
+
+    let mut state = ctx.data().state.lock().await;
+    let mut settings = state.settings(guild_id);
+
+    key.set(&mut settings, &draw_name, &value)?;
+
+    let (value, _) = key.describe(&ctx.data().config, &settings, &draw_name);
+
+    state.set_settings(guild_id, settings).await?;
+    drop(state);
+
+    // the random draw settings may have changed
+    ctx.data().reschedule.notify_waiters();
+
+    sync_guest_role(&ctx, guild_id, previous.monologues.guest_role_id).await?;
+
+    ctx.send(
+        CreateReply::default()
+            .content(format!(
+                "Set `{}`{} to {}",
+                key.name(),
+                draw_suffix(key, &draw_name),
+                value
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Reset a configuration option for this server back to its default
+#[poise::command(slash_command, guild_only)]
+async fn reset(
+    ctx: Context<'_>,
+    #[description = "The option to reset. Resets every option if omitted"]
+    #[autocomplete = "autocomplete_key"]
+    key: Option<String>,
+    #[description = "The random draw to reset. Only resets this draw's options if given"]
+    #[autocomplete = "super::trigger::autocomplete_draw"]
+    draw: Option<String>,
+) -> Result<()> {
+    crate::command::__trace_cmd!(ctx ctx, "config reset");
+
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+    let key = key.as_deref().map(parse_key).transpose()?;
+    let only_draw = draw.is_some();
+    let draw_name = draw_name(draw)?;
+
+    let previous = ctx.data().guild_config(guild_id).await;
+
+    let mut state = ctx.data().state.lock().await;
+    let mut settings = state.settings(guild_id);
+
+    let content = match key {
+        Some(key) => {
+            key.reset(&mut settings, &draw_name);
+
+            format!(
+                "Reset `{}`{} to its default",
+                key.name(),
+                draw_suffix(key, &draw_name)
+            )
+        }
+        None if only_draw => {
+            if draw_name == DEFAULT_DRAW {
+                settings.random_draw = RandomDrawSettings::default();
+            } else {
+                settings.draws.remove(&draw_name);
+            }
+
+            format!(
+                "Reset every option of draw `{}` to its default",
+                draw_name
+            )
+        }
+        None => {
+            settings = GuildSettings::default();
+
+            "Reset every option to its default".to_string()
+        }
+    };
+
+    state.set_settings(guild_id, settings).await?;
+    drop(state);
+
+    ctx.data().reschedule.notify_waiters();
+
+    sync_guest_role(&ctx, guild_id, previous.monologues.guest_role_id).await?;
+
+    ctx.send(CreateReply::default().content(content).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
+
+/// Move the permission of guests to send messages in every monologue channel
+/// of the guild over to the current guest role, if it changed from
+/// `previous`.
+///
+/// Channels only get the permission when they are created, see
+/// `create_channel_for`, so this keeps channels created before the guest
+/// role changed in line with newer ones.
+async fn sync_guest_role(
+    ctx: &Context<'_>,
+    guild_id: GuildId,
+    previous: Option<RoleId>,
+) -> Result<()> {
+    let config = ctx.data().guild_config(guild_id).await;
+    let current = config.monologues.guest_role_id;
+
+    if current == previous {
+        return Ok(());
+    }
+
+    // updating every channel can take longer than Discord waits for a
+    // response
+    ctx.defer_ephemeral().await?;
+
+    let channels = ctx.data().state.lock().await.get_channels(guild_id);
+    debug!(?guild_id, ?previous, ?current, "updating guest role of {} channels", channels.len());
+
+    for channel_id in channels {
+        let result = async {
+            if let Some(role_id) = previous {
+                channel_id
+                    .delete_permission(ctx.http(), PermissionOverwriteType::Role(role_id))
+                    .await?;
+            }
+
+            // channels that anyone may post in have no permissions set up
+            if let Some(role_id) = current.filter(|_| !config.monologues.allow_anyone) {
+                let permission = PermissionOverwrite {
+                    allow: Permissions::SEND_MESSAGES,
+                    deny: Permissions::empty(),
+                    kind: PermissionOverwriteType::Role(role_id),
+                };
+
+                channel_id.create_permission(ctx.http(), permission).await?;
+            }
+
+            Ok::<_, serenity::Error>(())
+        }
+        .await;
+
+        if let Err(err) = result {
+            warn!(?channel_id, "failed to update guest role permissions: {:?}", err);
+        }
+    }
+
+    Ok(())
+}
@@ -25,11 +25,12 @@ struct CreateChannel {
 
 /// Create a monologue channel for the provided user
 pub async fn create_channel_for(ctx: &Context<'_>, user: &User) -> Result<()> {
-    let config = &ctx.data().config;
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+    let config = ctx.data().guild_config(guild_id).await;
     let mut state = ctx.data().state.lock().await;
 
     // if the user has a channel already, tell the user and exit
-    if state.get_channel(user.id).is_some() {
+    if state.get_channel(guild_id, user.id).is_some() {
         trace!("channel already exists for {}", user.name);
         ctx.send(
             CreateReply::default()
@@ -40,8 +41,6 @@ pub async fn create_channel_for(ctx: &Context<'_>, user: &User) -> Result<()> {
         return Ok(());
     }
 
-    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
-
     let user_name = user.global_name.as_ref().unwrap_or(&user.name);
 
     let channel_name = user_name.replace(' ', "_");
@@ -49,7 +48,9 @@ pub async fn create_channel_for(ctx: &Context<'_>, user: &User) -> Result<()> {
     // if channel sorting is enabled, create the channel with the next position
     // immediately instead of moving it to the top after creation
     let position = if config.is_autosort_enabled() {
-        let next = utils::checked_next_position(ctx.serenity_context(), None, &mut state).await?;
+        let next =
+            utils::checked_next_position(ctx.serenity_context(), guild_id, None, &mut state)
+                .await?;
 
         Some(next)
     } else {
@@ -58,7 +59,7 @@ pub async fn create_channel_for(ctx: &Context<'_>, user: &User) -> Result<()> {
 
     let channel = CreateChannel {
         name: channel_name,
-        parent_id: ctx.data().config.monologues.category_id,
+        parent_id: config.monologues.category_id,
         position,
     };
     trace!(?channel, "creating channel");
@@ -76,7 +77,7 @@ pub async fn create_channel_for(ctx: &Context<'_>, user: &User) -> Result<()> {
         .await?;
 
     // set up permissions if configured to do so
-    if !ctx.data().config.monologues.allow_anyone {
+    if !config.monologues.allow_anyone {
         trace!("setting up permissions");
 
         // forbid @everyone from sending messages
@@ -98,13 +99,30 @@ pub async fn create_channel_for(ctx: &Context<'_>, user: &User) -> Result<()> {
         };
 
         channel.create_permission(ctx.http(), permission).await?;
+
+        // guests may post in every monologue channel
+        if let Some(role_id) = config.monologues.guest_role_id {
+            let permission = PermissionOverwrite {
+                allow: Permissions::SEND_MESSAGES,
+                deny: Permissions::empty(),
+                kind: PermissionOverwriteType::Role(role_id),
+            };
+
+            channel.create_permission(ctx.http(), permission).await?;
+        }
     }
 
     // associate the channel with the user
-    state.set_channel(user.id, channel.id).await?;
+    state.set_channel(guild_id, user.id, channel.id).await?;
+
+    // this may be the first channel in the guild, in which case it needs a
+    // random draw task of its own
+    ctx.data().reschedule.notify_waiters();
 
     if let Some(position) = position {
-        state.set_channel_position(channel.id, position).await?;
+        state
+            .set_channel_position(guild_id, channel.id, position)
+            .await?;
     }
 
     ctx.send(
@@ -0,0 +1,110 @@
+use chrono::Utc;
+use color_eyre::eyre::OptionExt;
+use poise::CreateReply;
+use serenity::all::{Mentionable, Message};
+
+use crate::{
+    data::{index::IndexedMessage, Context},
+    utils,
+};
+
+super::command! {
+    false, context_menu = "Show draw stats for this message";
+    /// Show how often a message was drawn and whether it can be drawn
+    pub async fn draw_stats(ctx: Context<'_>, message: Message) -> Result<()> {
+        let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+        let data = ctx.data();
+
+        if !data.state.lock().await.should_track(guild_id, message.channel_id) {
+            ctx.send(
+                CreateReply::default()
+                    .content("This message is not in a monologue channel, so it is never drawn")
+                    .ephemeral(true),
+            )
+            .await?;
+
+            return Ok(());
+        }
+
+        let guild_config = data.guild_config(guild_id).await;
+        let rejections = guild_config.filters.rejections(&message, Utc::now());
+        let indexed = data
+            .index
+            .lock()
+            .await
+            .contains(guild_id, message.channel_id, message.id);
+
+        let state = data.state.lock().await;
+
+        let mut lines = vec![match state.message_stats(guild_id, message.id) {
+            Some(stats) => format!(
+                "Drawn {} time{}, last {}",
+                stats.count,
+                if stats.count == 1 { "" } else { "s" },
+                utils::generate_discord_timestamp(stats.last_drawn_at, "R")
+            ),
+            None => "Never drawn".to_string(),
+        }];
+
+        if state.is_message_excluded(guild_id, message.channel_id, message.id) {
+            lines.push("Excluded from every draw by its author".to_string());
+        }
+
+        if state.is_message_flagged(guild_id, message.channel_id, message.id) {
+            lines.push("Flagged as a foreign post, so it is never drawn".to_string());
+        }
+
+        // the privacy choices of whoever's pool the message counts toward
+        // apply, which is a collaborator rather than the owner if configured
+        let pool = state
+            .pools(guild_id, guild_config.monologues.collaborator_posts)
+            .pool_of(message.channel_id, &IndexedMessage::from(&message));
+
+        if let Some(pool) = pool {
+            let privacy = state.privacy(guild_id, pool);
+
+            if privacy.exclude_draws {
+                lines.push(format!(
+                    "{} excluded their posts from random draws",
+                    pool.mention()
+                ));
+            }
+
+            if privacy.exclude_random {
+                lines.push(format!(
+                    "{} excluded their posts from `/random` and `/onthisday` used by others",
+                    pool.mention()
+                ));
+            }
+        }
+
+        for draw in &guild_config.draws {
+            let in_cooldown = state
+                .recent_draws(guild_id, &draw.name, draw.cooldown())
+                .iter()
+                .any(|record| record.message_id == message.id);
+
+            if in_cooldown {
+                lines.push(format!("In the cooldown of random draw `{}`", draw.name));
+            }
+        }
+
+        drop(state);
+
+        if !rejections.is_empty() {
+            lines.push("Not eligible because:".to_string());
+            lines.extend(rejections.iter().map(|rejection| format!("- {}", rejection)));
+        } else if !indexed {
+            lines.push("Eligible, but not indexed yet".to_string());
+        }
+
+        ctx.send(
+            CreateReply::default()
+                .content(lines.join("\n"))
+                .ephemeral(true),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
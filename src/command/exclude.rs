@@ -0,0 +1,45 @@
+use color_eyre::eyre::OptionExt;
+use poise::CreateReply;
+use serenity::all::Message;
+
+use crate::data::Context;
+
+super::command! {
+    false, context_menu = "Exclude from draws";
+    /// Exclude one of your messages from every draw, or include it again
+    pub async fn exclude(ctx: Context<'_>, message: Message) -> Result<()> {
+        let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+
+        // only the author decides whether their message is drawn, the same
+        // as with the exclude reaction
+        let content = if message.author.id != ctx.author().id {
+            "You can only exclude your own messages"
+        } else {
+            let mut state = ctx.data().state.lock().await;
+
+            if !state.should_track(guild_id, message.channel_id) {
+                "Only messages in monologue channels are drawn"
+            } else if state.is_message_flagged(guild_id, message.channel_id, message.id) {
+                "This message was flagged as a foreign post and is never drawn"
+            } else {
+                let excluded =
+                    !state.is_message_excluded(guild_id, message.channel_id, message.id);
+
+                state
+                    .set_message_excluded(guild_id, message.channel_id, message.id, excluded)
+                    .await?;
+
+                if excluded {
+                    "This message is now excluded from every draw. Use this again to include it"
+                } else {
+                    "This message can be drawn again"
+                }
+            }
+        };
+
+        ctx.send(CreateReply::default().content(content).ephemeral(true))
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use chrono::Utc;
+use color_eyre::eyre::{OptionExt, Result, WrapErr};
+use poise::CreateReply;
+use serenity::all::parse_message_url;
+
+use crate::data::Context;
+
+super::command! {
+    true, subcommands("test"), subcommand_required;
+    /// Work with the filters for which messages are eligible for random draws
+    ///
+    /// **Admin only**
+    pub async fn filters(ctx: Context<'_>) -> Result<()> {
+        // unreachable because a subcommand is required
+        Ok(())
+    }
+}
+
+/// Check whether a message is eligible for random draws and explain why
+#[poise::command(slash_command, guild_only)]
+async fn test(
+    ctx: Context<'_>,
+    #[description = "A link to the message to check"] message: String,
+) -> Result<()> {
+    crate::command::__trace_cmd!(ctx ctx, "filters test");
+
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+
+    let Some((link_guild_id, channel_id, message_id)) = parse_message_url(&message) else {
+        ctx.send(
+            CreateReply::default()
+                .content("That is not a valid message link")
+                .ephemeral(true),
+        )
+        .await?;
+
+        return Ok(());
+    };
+
+    if link_guild_id != guild_id {
+        ctx.send(
+            CreateReply::default()
+                .content("That message is not in this server")
+                .ephemeral(true),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    let message = ctx
+        .http()
+        .get_message(channel_id, message_id)
+        .await
+        .wrap_err("failed to get message")?;
+
+    let filters = &ctx.data().guild_config(guild_id).await.filters;
+    let rejections = filters.rejections(&message, Utc::now());
+
+    let mut lines = if rejections.is_empty() {
+        vec!["This message is eligible for random draws".to_string()]
+    } else {
+        let mut lines = vec!["This message is not eligible for random draws because:".to_string()];
+        lines.extend(rejections.iter().map(|rejection| format!("- {}", rejection)));
+        lines
+    };
+
+    // the filters only apply to messages in monologue channels, and messages
+    // indexed before the filters changed stay in the index until they are
+    // drawn or reindexed
+    if !ctx
+        .data()
+        .state
+        .lock()
+        .await
+        .should_track(guild_id, channel_id)
+    {
+        lines.push("It is not in a monologue channel, so it is never drawn".to_string());
+    } else {
+        let indexed = ctx
+            .data()
+            .index
+            .lock()
+            .await
+            .contains(guild_id, channel_id, message_id);
+
+        if indexed && !rejections.is_empty() {
+            lines.push(
+                "It is still indexed, but is removed when it is drawn or on the next `/reindex`"
+                    .to_string(),
+            );
+        } else if !indexed && rejections.is_empty() {
+            lines.push("It is not indexed yet, run `/reindex` to pick it up".to_string());
+        }
+    }
+
+    ctx.send(
+        CreateReply::default()
+            .content(lines.join("\n"))
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
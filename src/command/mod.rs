@@ -28,11 +28,21 @@ macro_rules! commands {
 }
 
 commands! {
+    collab,
+    config,
     create,
     create_for,
+    draw_stats,
+    exclude,
+    filters,
+    onthisday,
+    privacy,
     random,
+    reindex,
     remove,
     remove_for,
+    repost,
+    template,
     trigger,
 }
 
@@ -65,9 +75,35 @@ commands! {
 /// // then from another module
 /// let my_command = my_command::command(&config);
 /// ```
+///
+/// Additional arguments for the `poise::command` macro can be listed after the
+/// admin flag, e.g. `true, subcommands("get", "set"), subcommand_required;`.
+///
+/// Message context menu commands are defined by naming the menu entry right
+/// after the admin flag, e.g. `true, context_menu = "Repost";`. The function
+/// then takes the message as its second argument instead of slash command
+/// options.
 macro_rules! command {
     (
-        $is_admin:literal;
+        $is_admin:literal, context_menu = $menu_name:literal $(, $poise_arg:meta)*;
+        $($rest:tt)*
+    ) => {
+        $crate::command::command! {
+            @define $is_admin, [context_menu_command = $menu_name, guild_only $(, $poise_arg)*];
+            $($rest)*
+        }
+    };
+    (
+        $is_admin:literal $(, $poise_arg:meta)*;
+        $($rest:tt)*
+    ) => {
+        $crate::command::command! {
+            @define $is_admin, [slash_command, guild_only $(, $poise_arg)*];
+            $($rest)*
+        }
+    };
+    (
+        @define $is_admin:literal, [$($poise_arg:tt)*];
         $(#[$attr:meta])*
         pub async fn $name:ident(
             $(
@@ -86,7 +122,7 @@ macro_rules! command {
         }
 
         $(#[$attr])*
-        #[::poise::command(slash_command, guild_only)]
+        #[::poise::command($($poise_arg)*)]
         async fn $name($(
             $(#[$arg_attr])*
             $arg: $arg_ty,
@@ -0,0 +1,122 @@
+use chrono::Utc;
+use color_eyre::eyre::{OptionExt, Result};
+use poise::{command, CreateReply};
+use serenity::all::{Mentionable, User};
+
+use crate::{
+    data::{
+        config::{AppConfig, DrawStrategy, RandomDrawConfig, RepostFormat, Timezone, DEFAULT_DRAW},
+        Command, Context,
+    },
+    random_draw, utils,
+};
+
+// see `random.rs` for why this doesn't use the `command!` macro
+pub fn command(_config: &AppConfig) -> Command {
+    onthisday()
+}
+
+/// Get a random message posted on today's date in a previous year
+#[command(slash_command, guild_only)]
+pub async fn onthisday(
+    ctx: Context<'_>,
+    #[description = "The user whose monologue channel the message will be drawn from"] user: Option<
+        User,
+    >,
+) -> Result<()> {
+    crate::command::__trace_cmd!(ctx ctx, "onthisday");
+
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+
+    // use the same weighting, timezone and format as the default scheduled draw
+    let guild_config = ctx.data().guild_config(guild_id).await;
+    let (strategy, half_life_days, tz, format, upload_limit) = match guild_config.draw(DEFAULT_DRAW)
+    {
+        Some(random_draw) => (
+            random_draw.strategy,
+            random_draw.half_life_days,
+            *random_draw.timezone,
+            random_draw.format,
+            random_draw.reupload_limit_bytes(),
+        ),
+        None => (
+            DrawStrategy::default(),
+            RandomDrawConfig::default_half_life_days(),
+            *Timezone::default(),
+            RepostFormat::default(),
+            0,
+        ),
+    };
+
+    let now = Utc::now();
+    let today = now.with_timezone(&tz).date_naive();
+    let filters = &guild_config.filters;
+    let exclusions = ctx
+        .data()
+        .state
+        .lock()
+        .await
+        .exclusions(
+            guild_id,
+            Some(ctx.author().id),
+            guild_config.monologues.collaborator_posts,
+        );
+
+    // restrict the draw to the user's pool if one was specified
+    let pools = &exclusions.pools;
+    if let Some(user) = user.as_ref().filter(|user| !pools.has_pool(user.id)) {
+        trace!("no channel exists for {}", user.name);
+
+        ctx.send(
+            CreateReply::default()
+                .content(format!("No channel exists for <@{}>", user.id))
+                .ephemeral(true),
+        )
+        .await?;
+
+        return Ok(());
+    }
+    let user_id = user.as_ref().map(|user| user.id);
+
+    // drawing can fetch several messages that turn out to be deleted, and
+    // downloading attachments takes a while too, either of which can take
+    // longer than Discord waits for a response
+    ctx.defer().await?;
+
+    let Some(message) = random_draw::draw_message(guild_id, ctx.data(), ctx.http(), |index| {
+        index.random_message_weighted(guild_id, strategy, half_life_days, &exclusions, |channel_id, message| {
+            user_id.is_none_or(|id| pools.pool_of(channel_id, message) == Some(id))
+                && filters.allows_indexed(message, now)
+                && random_draw::is_on_this_day(*message.id.created_at(), today, tz)
+        })
+    })
+    .await?
+    else {
+        trace!("no messages posted on this day");
+
+        let content = match user {
+            Some(user) => format!("<@{}> posted nothing on this day in previous years", user.id),
+            None => "Nothing was posted on this day in previous years".to_string(),
+        };
+
+        ctx.send(CreateReply::default().content(content).ephemeral(true))
+            .await?;
+
+        return Ok(());
+    };
+
+    let uploads = utils::download_attachments(&message, upload_limit).await;
+
+    let years_ago = utils::years_ago(&message, tz);
+    let prefix = format!(
+        "On this day {} year{} ago, {} said:",
+        years_ago,
+        if years_ago == 1 { "" } else { "s" },
+        message.author.mention()
+    );
+
+    ctx.send(utils::format_repost(message, Some(prefix), format, uploads).into())
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use color_eyre::eyre::{OptionExt, Result};
+use poise::{command, CreateReply};
+
+use crate::{
+    data::{config::AppConfig, Command, Context},
+    handler::EXCLUDE_REACTION,
+};
+
+// see `random.rs` for why this doesn't use the `command!` macro
+pub fn command(_config: &AppConfig) -> Command {
+    privacy()
+}
+
+/// Choose whether your monologue channel can be drawn. Shows your choices if no option is given
+#[command(slash_command, guild_only)]
+pub async fn privacy(
+    ctx: Context<'_>,
+    #[description = "Exclude your channel from random draws"] exclude_draws: Option<bool>,
+    #[description = "Exclude your channel from /random and /onthisday used by others"]
+    exclude_random: Option<bool>,
+) -> Result<()> {
+    crate::command::__trace_cmd!(ctx ctx, "privacy");
+
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+    let user_id = ctx.author().id;
+
+    let privacy = {
+        let mut state = ctx.data().state.lock().await;
+        let mut privacy = state.privacy(guild_id, user_id);
+
+        if exclude_draws.is_some() || exclude_random.is_some() {
+            privacy.exclude_draws = exclude_draws.unwrap_or(privacy.exclude_draws);
+            privacy.exclude_random = exclude_random.unwrap_or(privacy.exclude_random);
+
+            state.set_privacy(guild_id, user_id, privacy).await?;
+        }
+
+        privacy
+    };
+
+    let describe = |excluded: bool| if excluded { "excluded" } else { "included" };
+
+    let content = format!(
+        "Your channel is {} from random draws and {} from `/random` and `/onthisday` used by others.\n\
+        React with {} to one of your messages to exclude just that message from every draw.",
+        describe(privacy.exclude_draws),
+        describe(privacy.exclude_random),
+        EXCLUDE_REACTION,
+    );
+
+    ctx.send(CreateReply::default().content(content).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
@@ -1,10 +1,14 @@
-use color_eyre::eyre::Result;
+use chrono::Utc;
+use color_eyre::eyre::{OptionExt, Result};
 use poise::{command, CreateReply};
 use serenity::all::User;
 
 use crate::{
-    data::{config::AppConfig, Command, Context},
-    utils,
+    data::{
+        config::{AppConfig, DrawStrategy, RandomDrawConfig, RepostFormat, DEFAULT_DRAW},
+        Command, Context,
+    },
+    random_draw, utils,
 };
 
 // the `command!` macro somehow alters the `Option<User>` in a way that breaks
@@ -28,30 +32,68 @@ pub async fn random(
 ) -> Result<()> {
     crate::command::__trace_cmd!(ctx ctx, "random");
 
-    let (channel_id, message_id) = if let Some(user) = user {
-        // if the user is specified, get a random message from their channel
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
 
-        // get their channel if it exists
-        let Some(user_channel) = ctx.data().state.lock().await.get_channel(user.id) else {
-            trace!("no channel exists for {}", user.name);
+    // use the same weighting and format as the default scheduled draw
+    let guild_config = ctx.data().guild_config(guild_id).await;
+    let filters = &guild_config.filters;
+    let now = Utc::now();
+    let exclusions = ctx
+        .data()
+        .state
+        .lock()
+        .await
+        .exclusions(
+            guild_id,
+            Some(ctx.author().id),
+            guild_config.monologues.collaborator_posts,
+        );
+    let (strategy, half_life_days, format, upload_limit) = match guild_config.draw(DEFAULT_DRAW)
+    {
+        Some(random_draw) => (
+            random_draw.strategy,
+            random_draw.half_life_days,
+            random_draw.format,
+            random_draw.reupload_limit_bytes(),
+        ),
+        None => (
+            DrawStrategy::default(),
+            RandomDrawConfig::default_half_life_days(),
+            RepostFormat::default(),
+            0,
+        ),
+    };
 
-            ctx.send(
-                CreateReply::default()
-                    .content(format!("No channel exists for <@{}>", user.id))
-                    .ephemeral(true),
-            )
-            .await?;
+    // make sure the user has a pool at all if one was specified
+    let pools = &exclusions.pools;
+    if let Some(user) = user.as_ref().filter(|user| !pools.has_pool(user.id)) {
+        trace!("no channel exists for {}", user.name);
 
-            return Ok(());
-        };
+        ctx.send(
+            CreateReply::default()
+                .content(format!("No channel exists for <@{}>", user.id))
+                .ephemeral(true),
+        )
+        .await?;
 
-        // get a random message from their channel if any exist
-        let Some(random) = ctx
-            .data()
-            .index
-            .lock()
-            .await
-            .random_message_from(user_channel)
+        return Ok(());
+    }
+
+    // drawing can fetch several messages that turn out to be deleted, and
+    // downloading attachments takes a while too, either of which can take
+    // longer than Discord waits for a response
+    ctx.defer().await?;
+
+    let message = if let Some(user) = user {
+        // if the user is specified, get a random message from their pool, which
+        // is their channel and possibly their posts as a collaborator
+        let Some(message) = random_draw::draw_message(guild_id, ctx.data(), ctx.http(), |index| {
+            index.random_message_weighted(guild_id, strategy, half_life_days, &exclusions, |channel_id, message| {
+                pools.pool_of(channel_id, message) == Some(user.id)
+                    && filters.allows_indexed(message, now)
+            })
+        })
+        .await?
         else {
             trace!("channel for {} contains no messages", user.name);
 
@@ -65,11 +107,17 @@ pub async fn random(
             return Ok(());
         };
 
-        (user_channel, random)
+        message
     } else {
         // if the user is not specified, get a random message from any channel,
         // if any exist
-        let Some((channel_id, message_id)) = ctx.data().index.lock().await.random_message() else {
+        let Some(message) = random_draw::draw_message(guild_id, ctx.data(), ctx.http(), |index| {
+            index.random_message_weighted(guild_id, strategy, half_life_days, &exclusions, |_, message| {
+                filters.allows_indexed(message, now)
+            })
+        })
+        .await?
+        else {
             trace!("no messages in any channel");
             ctx.send(
                 CreateReply::default()
@@ -80,15 +128,13 @@ pub async fn random(
             return Ok(());
         };
 
-        (channel_id, message_id)
+        message
     };
 
-    trace!(?channel_id, ?message_id, "fetching message");
-    // fetch the message object
-    let message = channel_id.message(ctx, message_id).await?;
+    let uploads = utils::download_attachments(&message, upload_limit).await;
 
     // reply with the formatted message content
-    ctx.send(CreateReply::default().content(utils::format_repost_content(message, None)))
+    ctx.send(utils::format_repost(message, None, format, uploads).into())
         .await?;
 
     Ok(())
@@ -0,0 +1,69 @@
+use color_eyre::eyre::{OptionExt, Result};
+use poise::{command, CreateReply};
+use serenity::all::User;
+
+use crate::{
+    data::{config::AppConfig, Command, Context},
+    reconcile,
+};
+
+// see `random.rs` for why this doesn't use the `command!` macro
+pub fn command(config: &AppConfig) -> Command {
+    let mut cmd = reindex();
+
+    cmd.default_member_permissions = *config.admin_permissions;
+
+    cmd
+}
+
+/// Re-check indexed messages against Discord and fix any differences
+///
+/// **Admin only**
+#[command(slash_command, guild_only)]
+pub async fn reindex(
+    ctx: Context<'_>,
+    #[description = "The user whose monologue channel to re-check. Checks every channel if omitted"]
+    user: Option<User>,
+) -> Result<()> {
+    crate::command::__trace_cmd!(ctx ctx, "reindex");
+
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+
+    let channels = {
+        let state = ctx.data().state.lock().await;
+
+        match &user {
+            Some(user) => match state.get_channel(guild_id, user.id) {
+                Some(channel_id) => vec![channel_id],
+                None => {
+                    drop(state);
+
+                    ctx.send(
+                        CreateReply::default()
+                            .content(format!("No channel exists for <@{}>", user.id))
+                            .ephemeral(true),
+                    )
+                    .await?;
+
+                    return Ok(());
+                }
+            },
+            None => state.get_channels(guild_id),
+        }
+    };
+
+    // walking every channel can easily take longer than the interaction
+    // timeout
+    ctx.defer_ephemeral().await?;
+
+    let report = reconcile::reconcile_guild(ctx.data(), ctx.http(), guild_id, channels).await?;
+
+    ctx.send(
+        CreateReply::default()
+            .content(format!("Reindex complete: {}", report))
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{OptionExt, Result};
 use poise::CreateReply;
 use serenity::all::User;
 
@@ -16,6 +16,8 @@ super::command! {
 
 /// Remove the monologue channel for the provided user if one exists
 pub async fn remove_channel_for(ctx: &Context<'_>, user: &User) -> Result<()> {
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+
     // if the user has a channel, remove it and return it, otherwise send a
     // reply stating that no channel exists
     let Some(channel_id) = ctx
@@ -23,7 +25,7 @@ pub async fn remove_channel_for(ctx: &Context<'_>, user: &User) -> Result<()> {
         .state
         .lock()
         .await
-        .remove_channel_for(&user.id)
+        .remove_channel_for(guild_id, &user.id)
         .await?
     else {
         trace!("no monologue channel exists for {}", user.name);
@@ -55,7 +57,7 @@ pub async fn remove_channel_for(ctx: &Context<'_>, user: &User) -> Result<()> {
         .index
         .lock()
         .await
-        .remove_channel(channel_id)
+        .remove_channel(guild_id, channel_id)
         .await?;
 
     ctx.send(
@@ -0,0 +1,106 @@
+use color_eyre::eyre::OptionExt;
+use poise::CreateReply;
+use serenity::all::{Mentionable, Message};
+
+use crate::{
+    data::{config::DEFAULT_DRAW, index::IndexedMessage, state::DrawSource, Context},
+    random_draw, utils,
+};
+
+super::command! {
+    true, context_menu = "Repost to draw channel";
+    /// Repost a monologue message to the channel of the only or default random draw
+    ///
+    /// **Admin only**
+    pub async fn repost(ctx: Context<'_>, message: Message) -> Result<()> {
+        let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+
+        let guild_config = ctx.data().guild_config(guild_id).await;
+
+        let random_draw = if guild_config.draws.len() == 1 {
+            guild_config.draws.first()
+        } else {
+            guild_config.draw(DEFAULT_DRAW)
+        };
+
+        let Some(random_draw) = random_draw else {
+            trace!("random draw is not configured");
+
+            ctx.send(
+                CreateReply::default()
+                    .content("Random draw is not configured")
+                    .ephemeral(true),
+            )
+            .await?;
+
+            return Ok(());
+        };
+
+        // the draw channel may be inherited from the config file and belong to
+        // a different guild than the one the command was executed in
+        if utils::guild_of(ctx.http(), random_draw.channel_id).await? != guild_id {
+            trace!("random draw channel is not in this guild");
+
+            ctx.send(
+                CreateReply::default()
+                    .content("Random draw is not configured for this server")
+                    .ephemeral(true),
+            )
+            .await?;
+
+            return Ok(());
+        }
+
+        let refusal = {
+            let state = ctx.data().state.lock().await;
+
+            if !state.should_track(guild_id, message.channel_id) {
+                Some("Only messages in monologue channels can be reposted")
+            } else if state.is_message_flagged(guild_id, message.channel_id, message.id) {
+                Some("This message was flagged as a foreign post and can't be reposted")
+            } else if !state
+                .exclusions(guild_id, None, guild_config.monologues.collaborator_posts)
+                .allows(message.channel_id, &IndexedMessage::from(&message))
+            {
+                Some("The author excluded this message from random draws")
+            } else {
+                None
+            }
+        };
+
+        if let Some(refusal) = refusal {
+            ctx.send(CreateReply::default().content(refusal).ephemeral(true))
+                .await?;
+
+            return Ok(());
+        }
+
+        // uploading attachments again can take longer than Discord waits for
+        // a response
+        ctx.defer_ephemeral().await?;
+
+        // messages from interactions don't include their guild, which links
+        // to the message need
+        let mut message = message;
+        message.guild_id = Some(guild_id);
+
+        random_draw::repost(
+            random_draw,
+            guild_id,
+            ctx.data(),
+            ctx.http(),
+            message,
+            DrawSource::Manual,
+        )
+        .await?;
+
+        ctx.send(
+            CreateReply::default()
+                .content(format!("Reposted to {}", random_draw.channel_id.mention()))
+                .ephemeral(true),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
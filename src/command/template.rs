@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use color_eyre::eyre::{OptionExt, Result, WrapErr};
+use poise::CreateReply;
+use serenity::all::parse_message_url;
+
+use crate::{
+    data::{
+        config::{RepostFormat, Timezone, DEFAULT_DRAW},
+        Context,
+    },
+    random_draw,
+    template::Template,
+    utils,
+};
+
+super::command! {
+    true, subcommands("preview"), subcommand_required;
+    /// Work with random draw message templates
+    ///
+    /// **Admin only**
+    pub async fn template(ctx: Context<'_>) -> Result<()> {
+        // unreachable because a subcommand is required
+        Ok(())
+    }
+}
+
+/// Preview a random draw message template with a chosen or random message
+#[poise::command(slash_command, guild_only)]
+async fn preview(
+    ctx: Context<'_>,
+    #[description = "The template to preview"] template: String,
+    #[description = "A link to the message to preview the template with. Picks a random message if omitted"]
+    message: Option<String>,
+) -> Result<()> {
+    crate::command::__trace_cmd!(ctx ctx, "template preview");
+
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+
+    let (template, unknown) = Template::parse_lenient(&template)?;
+
+    // fetching the message and the names used by the template can take a while
+    ctx.defer_ephemeral().await?;
+
+    let message = match message {
+        Some(link) => {
+            let Some((link_guild_id, channel_id, message_id)) = parse_message_url(&link) else {
+                ctx.send(
+                    CreateReply::default()
+                        .content("That is not a valid message link")
+                        .ephemeral(true),
+                )
+                .await?;
+
+                return Ok(());
+            };
+
+            if link_guild_id != guild_id {
+                ctx.send(
+                    CreateReply::default()
+                        .content("That message is not in this server")
+                        .ephemeral(true),
+                )
+                .await?;
+
+                return Ok(());
+            }
+
+            let mut message = ctx
+                .http()
+                .get_message(channel_id, message_id)
+                .await
+                .wrap_err("failed to get message")?;
+            message.guild_id = Some(guild_id);
+
+            message
+        }
+        None => {
+            let guild_config = ctx.data().guild_config(guild_id).await;
+            let filters = &guild_config.filters;
+            let now = Utc::now();
+            let exclusions = ctx.data().state.lock().await.exclusions(
+                guild_id,
+                Some(ctx.author().id),
+                guild_config.monologues.collaborator_posts,
+            );
+
+            let Some(message) =
+                random_draw::draw_message(guild_id, ctx.data(), ctx.http(), |index| {
+                    index.random_message_matching(guild_id, &exclusions, |_, message| {
+                        filters.allows_indexed(message, now)
+                    })
+                })
+                .await?
+            else {
+                ctx.send(
+                    CreateReply::default()
+                        .content("No messages in any channel")
+                        .ephemeral(true),
+                )
+                .await?;
+
+                return Ok(());
+            };
+
+            message
+        }
+    };
+
+    // preview the template the way the default draw would post it
+    let guild_config = ctx.data().guild_config(guild_id).await;
+    let draw = guild_config.draw(DEFAULT_DRAW);
+    let tz = draw.map_or_else(|| *Timezone::default(), |d| *d.timezone);
+    let format = draw.map_or_else(RepostFormat::default, |d| d.format);
+    let draw_number = ctx.data().state.lock().await.draw_count(guild_id, DEFAULT_DRAW) + 1;
+
+    let prefix =
+        random_draw::render_template(&template, &message, tz, Some(draw_number), ctx.http()).await;
+
+    let reply: CreateReply = utils::format_repost(message, Some(prefix), format, HashMap::new()).into();
+
+    ctx.send(reply.ephemeral(true)).await?;
+
+    if !unknown.is_empty() {
+        let unknown = unknown
+            .iter()
+            .map(|name| format!("`{{{}}}`", name))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        ctx.send(
+            CreateReply::default()
+                .content(format!(
+                    "Unknown placeholders, which were left empty: {}",
+                    unknown
+                ))
+                .ephemeral(true),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
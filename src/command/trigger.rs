@@ -1,25 +1,93 @@
-use poise::CreateReply;
+use color_eyre::eyre::{OptionExt, Result};
+use poise::{command, CreateReply};
 
-use crate::{data::Context, random_draw::do_random_draw};
+use crate::{
+    data::{
+        config::{AppConfig, DEFAULT_DRAW},
+        Command, Context,
+    },
+    random_draw::do_random_draw,
+    utils,
+};
 
-super::command! {
-    true;
-    /// Trigger a random draw
-    ///
-    /// **Admin only**
-    pub async fn trigger(ctx: Context<'_>) -> Result<()> {
-        let Some(random_draw) = &ctx.data().config.random_draw else {
-            trace!("random draw is not configured");
+// see `random.rs` for why this doesn't use the `command!` macro
+pub fn command(config: &AppConfig) -> Command {
+    let mut cmd = trigger();
 
-            ctx.send(CreateReply::default().content("Random draw is not configured").ephemeral(true)).await?;
+    cmd.default_member_permissions = *config.admin_permissions;
 
-            return Ok(());
+    cmd
+}
+
+/// Trigger a random draw
+///
+/// **Admin only**
+#[command(slash_command, guild_only)]
+pub async fn trigger(
+    ctx: Context<'_>,
+    #[description = "The random draw to trigger. Defaults to the only or default draw"]
+    #[autocomplete = "autocomplete_draw"]
+    draw: Option<String>,
+) -> Result<()> {
+    crate::command::__trace_cmd!(ctx ctx, "trigger");
+
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+
+    let guild_config = ctx.data().guild_config(guild_id).await;
+
+    let random_draw = match &draw {
+        Some(name) => guild_config.draw(name),
+        None if guild_config.draws.len() == 1 => guild_config.draws.first(),
+        None => guild_config.draw(DEFAULT_DRAW),
+    };
+
+    let Some(random_draw) = random_draw else {
+        trace!(?draw, "random draw is not configured");
+
+        let content = match (draw, guild_config.draws.is_empty()) {
+            (_, true) => "Random draw is not configured".to_string(),
+            (Some(name), false) => format!("No random draw named `{}` is configured", name),
+            (None, false) => "Multiple random draws are configured, choose one to trigger".to_string(),
         };
 
-        do_random_draw(random_draw, ctx.data(), ctx.http()).await?;
+        ctx.send(CreateReply::default().content(content).ephemeral(true)).await?;
+
+        return Ok(());
+    };
 
-        ctx.send(CreateReply::default().content("Random draw triggered").ephemeral(true)).await?;
+    // the draw channel may be inherited from the config file and belong to
+    // a different guild than the one the command was executed in
+    if utils::guild_of(ctx.http(), random_draw.channel_id).await? != guild_id {
+        trace!("random draw channel is not in this guild");
 
-        Ok(())
+        ctx.send(CreateReply::default().content("Random draw is not configured for this server").ephemeral(true)).await?;
+
+        return Ok(());
     }
+
+    // the draw may upload attachments again, which can take longer than
+    // Discord waits for a response
+    ctx.defer_ephemeral().await?;
+
+    let outcome = do_random_draw(random_draw, guild_id, ctx.data(), ctx.http()).await?;
+
+    ctx.send(CreateReply::default().content(outcome.describe()).ephemeral(true)).await?;
+
+    Ok(())
+}
+
+/// Suggest the names of the random draws configured in the current guild
+pub(super) async fn autocomplete_draw(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Vec::new();
+    };
+
+    ctx.data()
+        .guild_config(guild_id)
+        .await
+        .draws
+        .iter()
+        .map(|draw| draw.name.clone())
+        .filter(|name| name.starts_with(partial))
+        .collect()
 }
@@ -1,14 +1,17 @@
-use std::{ops::Deref, path::PathBuf, str::FromStr};
+use std::{collections::HashSet, fmt, ops::Deref, path::PathBuf, str::FromStr};
 
+use chrono::{DateTime, Datelike, Utc, Weekday};
 use chrono_tz::Tz;
-use color_eyre::eyre::{Context, Result};
+use color_eyre::eyre::{bail, Context, Result};
 use figment::{
     providers::{Env, Format, Toml},
     Figment,
 };
-use serenity::all::{ChannelId, Permissions};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
+use serenity::all::{ChannelId, Message, Permissions, RoleId};
+
+use crate::{data::state::Cooldown, filter::Filters, template::Template};
 
 #[derive(Debug, Clone, Copy, Deserialize)]
 #[serde(transparent)]
@@ -39,15 +42,70 @@ pub struct AppConfig {
     pub admin_permissions: AdminPermissions,
     /// The data folder to store state
     pub state_dir: PathBuf,
+    /// Where to persist the state and index within `state_dir`
+    #[serde(default)]
+    pub storage: StorageBackend,
     /// The configuration for the random draw feature
     #[serde(default)]
     pub random_draw: Option<RandomDrawConfig>,
+    /// Additional named random draws, each running on its own schedule
+    #[serde(default)]
+    pub random_draws: Vec<RandomDrawConfig>,
     /// Configuration for monologue channels
     #[serde(default)]
     pub monologues: MonologuesConfig,
+    /// Configuration for the message index
+    #[serde(default)]
+    pub index: IndexConfig,
+    /// Which messages are eligible for random draws
+    #[serde(default)]
+    pub filters: Filters,
 }
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Deserialize)]
+pub struct IndexConfig {
+    /// How often to check the index for messages that were deleted or edited
+    /// without the bot noticing, in hours. Zero disables the check.
+    #[serde(default = "IndexConfig::default_reconcile_interval_hours")]
+    pub reconcile_interval_hours: u64,
+    /// How many messages to try when drawing a random message before giving
+    /// up. Drawn messages that no longer exist are removed from the index
+    /// before trying again.
+    #[serde(default = "IndexConfig::default_draw_attempts")]
+    pub draw_attempts: u32,
+}
+
+impl IndexConfig {
+    fn default_reconcile_interval_hours() -> u64 {
+        24
+    }
+
+    fn default_draw_attempts() -> u32 {
+        5
+    }
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            reconcile_interval_hours: Self::default_reconcile_interval_hours(),
+            draw_attempts: Self::default_draw_attempts(),
+        }
+    }
+}
+
+/// The available backends for persisting the state and index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// JSON files in the state directory
+    #[default]
+    Json,
+    /// An embedded SQLite database in the state directory
+    Sqlite,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct Timezone(chrono_tz::Tz);
 
@@ -77,22 +135,468 @@ impl Deref for Timezone {
     }
 }
 
-#[derive(Debug, Deserialize)]
+impl FromStr for Timezone {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Tz::from_str(s)
+            .map(Timezone)
+            .map_err(|err| color_eyre::eyre::eyre!("invalid timezone: {err}"))
+    }
+}
+
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.name())
+    }
+}
+
+/// A range of times as `(after, before)`, where either end may be open
+pub type TimeRange = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// The name of the random draw configured in the `random_draw` section
+pub const DEFAULT_DRAW: &str = "default";
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct RandomDrawConfig {
+    /// The name used to refer to this draw, e.g. in `/trigger`
+    #[serde(default = "RandomDrawConfig::default_name")]
+    pub name: String,
     /// The channel ID where the bot will send messages
     pub channel_id: ChannelId,
     /// A cron schedule for when to trigger the random draws
     pub schedule: Schedule,
-    /// A list of messages to prefix each random draw with
+    /// A list of message templates to prefix each random draw with, see
+    /// [`crate::template`]
     #[serde(default)]
-    pub messages: Vec<String>,
+    pub messages: Vec<DrawMessage>,
     /// The timezone to use when formatting timestamps and for the random draw (if enabled)
     #[serde(default)]
     pub timezone: Timezone,
+    /// How messages are weighted when drawing
+    #[serde(default)]
+    pub strategy: DrawStrategy,
+    /// The age, in days, at which a message is half as likely to be drawn as a
+    /// new one. Only used by [`DrawStrategy::Recency`]
+    #[serde(default = "RandomDrawConfig::default_half_life_days")]
+    pub half_life_days: f64,
+    /// The number of draws during which a reposted message can't be drawn
+    /// again
+    #[serde(default)]
+    pub cooldown_draws: usize,
+    /// The number of hours during which a reposted message can't be drawn
+    /// again
+    #[serde(default)]
+    pub cooldown_hours: u32,
+    /// Exclude every message by the author of a message in the cooldown
+    /// instead of just the message itself
+    #[serde(default)]
+    pub cooldown_authors: bool,
+    /// What to do when no messages were posted since the last draw
+    #[serde(default)]
+    pub fallback: FallbackPolicy,
+    /// The message to post with [`FallbackPolicy::Message`]
+    #[serde(default = "RandomDrawConfig::default_fallback_message")]
+    pub fallback_message: String,
+    /// Only draw messages that are at least this many days old
+    #[serde(default)]
+    pub min_age_days: Option<u32>,
+    /// Only draw messages that are at most this many days old
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+    /// Only draw messages from these monologue channels. Draws from every
+    /// channel if empty
+    #[serde(default)]
+    pub source_channels: Vec<ChannelId>,
+    /// What to do about scheduled draws that were missed while the bot was
+    /// offline
+    #[serde(default)]
+    pub catch_up: CatchUpPolicy,
+    /// Only draw messages posted on today's date in previous years. Takes
+    /// precedence over the age limits
+    #[serde(default)]
+    pub on_this_day: bool,
+    /// How reposted messages are presented
+    #[serde(default)]
+    pub format: RepostFormat,
+    /// Upload the attachments of reposted messages again instead of linking
+    /// them, because Discord's attachment links expire
+    #[serde(default)]
+    pub reupload_attachments: bool,
+    /// The maximum combined size, in megabytes, of the attachments uploaded
+    /// with a single repost. Attachments beyond it are linked
+    #[serde(default = "RandomDrawConfig::default_reupload_limit_mb")]
+    pub reupload_limit_mb: u32,
+}
+
+impl RandomDrawConfig {
+    fn default_name() -> String {
+        DEFAULT_DRAW.to_string()
+    }
+
+    pub(crate) fn default_half_life_days() -> f64 {
+        30.0
+    }
+
+    pub(crate) fn default_fallback_message() -> String {
+        "Nothing new was posted since the last draw".to_string()
+    }
+
+    pub(crate) fn default_reupload_limit_mb() -> u32 {
+        10
+    }
+
+    /// The maximum combined size of the attachments uploaded with a repost,
+    /// or zero if attachments aren't uploaded again
+    pub fn reupload_limit_bytes(&self) -> u64 {
+        if self.reupload_attachments {
+            self.reupload_limit_mb as u64 * 1024 * 1024
+        } else {
+            0
+        }
+    }
+
+    pub fn cooldown(&self) -> Cooldown {
+        Cooldown {
+            draws: self.cooldown_draws,
+            duration: chrono::Duration::hours(self.cooldown_hours.into()),
+        }
+    }
+
+    /// The range of creation times to draw messages from, if a minimum or
+    /// maximum age is set. Either end may be open.
+    pub fn window(&self, now: DateTime<Utc>) -> Option<TimeRange> {
+        if self.min_age_days.is_none() && self.max_age_days.is_none() {
+            return None;
+        }
+
+        let ago = |days: u32| now - chrono::Duration::days(days.into());
+
+        Some((self.max_age_days.map(ago), self.min_age_days.map(ago)))
+    }
+}
+
+/// What a random draw does about scheduled draws that were missed while the
+/// bot was offline
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CatchUpPolicy {
+    /// Wait for the next scheduled draw
+    #[default]
+    Skip,
+    /// Run a single draw to make up for every missed draw
+    Once,
+    /// Run one draw for each missed draw
+    All,
+}
+
+impl FromStr for CatchUpPolicy {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "skip" => Ok(CatchUpPolicy::Skip),
+            "once" => Ok(CatchUpPolicy::Once),
+            "all" => Ok(CatchUpPolicy::All),
+            _ => Err(color_eyre::eyre::eyre!(
+                "catch up policy must be one of `skip`, `once` or `all`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CatchUpPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CatchUpPolicy::Skip => "skip",
+            CatchUpPolicy::Once => "once",
+            CatchUpPolicy::All => "all",
+        })
+    }
+}
+
+/// A message template to prefix random draws with, along with how likely it
+/// is to be picked and which drawn messages it can be used for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawDrawMessage", into = "RawDrawMessage")]
+pub struct DrawMessage {
+    pub template: Template,
+    /// How likely the message is to be picked relative to the others
+    pub weight: f64,
+    /// Only use the message if the drawn message does or doesn't have
+    /// attachments
+    pub has_attachments: Option<bool>,
+    /// Only use the message if the drawn message has at least this many
+    /// characters
+    pub min_length: Option<usize>,
+    /// Only use the message if the drawn message has at most this many
+    /// characters
+    pub max_length: Option<usize>,
+    /// Only use the message on these days of the week, in the timezone of the
+    /// draw. Any day if empty
+    pub days: Vec<Weekday>,
+}
+
+impl DrawMessage {
+    /// Check if the message can be used to prefix `message` when drawn at
+    /// `now`, in the timezone of the draw
+    pub fn matches(&self, message: &Message, now: DateTime<Tz>) -> bool {
+        let length = message.content.chars().count();
+
+        self.has_attachments
+            .is_none_or(|has| has == !message.attachments.is_empty())
+            && self.min_length.is_none_or(|min| length >= min)
+            && self.max_length.is_none_or(|max| length <= max)
+            && (self.days.is_empty() || self.days.contains(&now.weekday()))
+    }
+}
+
+/// A [`DrawMessage`] as it is written in the config. Either just the template
+/// or a table with the template and its options
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum RawDrawMessage {
+    Template(String),
+    Options(RawDrawMessageOptions),
+}
+
+// implemented by hand instead of with `#[serde(untagged)]` so mistakes in the
+// options, like misspelled names, are reported instead of a generic error
+impl<'de> Deserialize<'de> for RawDrawMessage {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = RawDrawMessage;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a template or a table with a `template`")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(RawDrawMessage::Template(v.to_string()))
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> Result<Self::Value, A::Error> {
+                RawDrawMessageOptions::deserialize(serde::de::value::MapAccessDeserializer::new(
+                    map,
+                ))
+                .map(RawDrawMessage::Options)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RawDrawMessageOptions {
+    template: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    weight: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    has_attachments: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    days: Vec<String>,
+}
+
+impl TryFrom<RawDrawMessage> for DrawMessage {
+    type Error = color_eyre::eyre::Report;
+
+    fn try_from(raw: RawDrawMessage) -> Result<Self> {
+        let options = match raw {
+            RawDrawMessage::Template(template) => {
+                return Ok(DrawMessage {
+                    template: template.parse()?,
+                    weight: 1.0,
+                    has_attachments: None,
+                    min_length: None,
+                    max_length: None,
+                    days: Vec::new(),
+                })
+            }
+            RawDrawMessage::Options(options) => options,
+        };
+
+        let weight = options.weight.unwrap_or(1.0);
+        if !(weight > 0.0 && weight.is_finite()) {
+            bail!("message weight must be a positive number");
+        }
+
+        let days = options
+            .days
+            .iter()
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .map_err(|_| color_eyre::eyre::eyre!("invalid day of the week `{}`", day))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(DrawMessage {
+            template: options.template.parse()?,
+            weight,
+            has_attachments: options.has_attachments,
+            min_length: options.min_length,
+            max_length: options.max_length,
+            days,
+        })
+    }
+}
+
+impl From<DrawMessage> for RawDrawMessage {
+    fn from(message: DrawMessage) -> Self {
+        let is_plain = message.weight == 1.0
+            && message.has_attachments.is_none()
+            && message.min_length.is_none()
+            && message.max_length.is_none()
+            && message.days.is_empty();
+
+        if is_plain {
+            return RawDrawMessage::Template(message.template.to_string());
+        }
+
+        RawDrawMessage::Options(RawDrawMessageOptions {
+            template: message.template.to_string(),
+            weight: Some(message.weight).filter(|&weight| weight != 1.0),
+            has_attachments: message.has_attachments,
+            min_length: message.min_length,
+            max_length: message.max_length,
+            days: message.days.iter().map(ToString::to_string).collect(),
+        })
+    }
+}
+
+/// How a drawn message is reposted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepostFormat {
+    /// The message content as plain text, followed by its attachment URLs
+    #[default]
+    Text,
+    /// An embed with the author, timestamp, a link to the original message and
+    /// its attachments
+    Embed,
+}
+
+impl FromStr for RepostFormat {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "text" => Ok(RepostFormat::Text),
+            "embed" => Ok(RepostFormat::Embed),
+            _ => Err(color_eyre::eyre::eyre!(
+                "repost format must be one of `text` or `embed`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RepostFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RepostFormat::Text => "text",
+            RepostFormat::Embed => "embed",
+        })
+    }
+}
+
+/// What a random draw does when no messages were posted since the last draw
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FallbackPolicy {
+    /// Post nothing
+    #[default]
+    Skip,
+    /// Draw from every message instead
+    AllTime,
+    /// Draw from the last week instead, then from the last month
+    Widening,
+    /// Post the configured fallback message
+    Message,
+}
+
+impl FromStr for FallbackPolicy {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "skip" => Ok(FallbackPolicy::Skip),
+            "all_time" => Ok(FallbackPolicy::AllTime),
+            "widening" => Ok(FallbackPolicy::Widening),
+            "message" => Ok(FallbackPolicy::Message),
+            _ => Err(color_eyre::eyre::eyre!(
+                "fallback must be one of `skip`, `all_time`, `widening` or `message`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FallbackPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FallbackPolicy::Skip => "skip",
+            FallbackPolicy::AllTime => "all_time",
+            FallbackPolicy::Widening => "widening",
+            FallbackPolicy::Message => "message",
+        })
+    }
+}
+
+/// How messages are weighted when drawing a random message
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrawStrategy {
+    /// Every message is equally likely, so busy channels are drawn more often
+    #[default]
+    Message,
+    /// Every channel is equally likely, regardless of how many messages it has
+    Channel,
+    /// Channels are drawn with a probability inversely proportional to their
+    /// number of messages, favoring quieter channels
+    InverseActivity,
+    /// Newer messages are more likely, with the weight of a message halving
+    /// every `half_life_days`
+    Recency,
+}
+
+impl FromStr for DrawStrategy {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "message" => Ok(DrawStrategy::Message),
+            "channel" => Ok(DrawStrategy::Channel),
+            "inverse_activity" => Ok(DrawStrategy::InverseActivity),
+            "recency" => Ok(DrawStrategy::Recency),
+            _ => Err(color_eyre::eyre::eyre!(
+                "strategy must be one of `message`, `channel`, `inverse_activity` or `recency`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DrawStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DrawStrategy::Message => "message",
+            DrawStrategy::Channel => "channel",
+            DrawStrategy::InverseActivity => "inverse_activity",
+            DrawStrategy::Recency => "recency",
+        })
+    }
 }
 
 #[serde_as]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Schedule(#[serde_as(as = "DisplayFromStr")] cron::Schedule);
 
 impl Deref for Schedule {
@@ -103,7 +607,23 @@ impl Deref for Schedule {
     }
 }
 
-#[derive(Debug, Default, Deserialize)]
+impl FromStr for Schedule {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        cron::Schedule::from_str(s)
+            .map(Schedule)
+            .wrap_err("invalid cron schedule")
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct MonologuesConfig {
     /// The category ID for the monologue channels
     #[serde(default)]
@@ -114,6 +634,99 @@ pub struct MonologuesConfig {
     /// Whether or not to disable auto-sorting of monologue channels based on activity
     #[serde(default)]
     pub disable_sorting: bool,
+    /// What to do about messages posted in a monologue channel by anyone but
+    /// its owner
+    #[serde(default)]
+    pub enforcement: Enforcement,
+    /// Members with this role may post in every monologue channel
+    #[serde(default)]
+    pub guest_role_id: Option<RoleId>,
+    /// Allow anyone to reply to messages in a monologue channel
+    #[serde(default)]
+    pub allow_replies: bool,
+    /// Send a direct message explaining why a foreign post was flagged or
+    /// deleted
+    #[serde(default)]
+    pub notify_foreign: bool,
+    /// Whose pool the posts of collaborators count toward
+    #[serde(default)]
+    pub collaborator_posts: CollaboratorPosts,
+}
+
+/// Whose pool of messages the posts of a collaborator in someone else's
+/// monologue channel count toward. A user's pool is what `/random` and
+/// `/onthisday` draw from when they are named, and what their privacy choices
+/// apply to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollaboratorPosts {
+    /// The posts count toward the owner of the channel, like their own posts
+    #[default]
+    Owner,
+    /// The posts count toward the collaborator who posted them
+    Author,
+}
+
+impl FromStr for CollaboratorPosts {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "owner" => Ok(CollaboratorPosts::Owner),
+            "author" => Ok(CollaboratorPosts::Author),
+            _ => Err(color_eyre::eyre::eyre!(
+                "collaborator posts must be one of `owner` or `author`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CollaboratorPosts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CollaboratorPosts::Owner => "owner",
+            CollaboratorPosts::Author => "author",
+        })
+    }
+}
+
+/// What happens to messages posted in a monologue channel by anyone but its
+/// owner. Guests and, if allowed, replies are never affected
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Enforcement {
+    /// Leave foreign posts alone
+    #[default]
+    Off,
+    /// React to foreign posts and keep them out of the index
+    Flag,
+    /// Delete foreign posts
+    Delete,
+}
+
+impl FromStr for Enforcement {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "off" => Ok(Enforcement::Off),
+            "flag" => Ok(Enforcement::Flag),
+            "delete" => Ok(Enforcement::Delete),
+            _ => Err(color_eyre::eyre::eyre!(
+                "enforcement must be one of `off`, `flag` or `delete`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Enforcement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Enforcement::Off => "off",
+            Enforcement::Flag => "flag",
+            Enforcement::Delete => "delete",
+        })
+    }
 }
 
 impl AppConfig {
@@ -133,10 +746,30 @@ impl AppConfig {
             .merge(Env::prefixed("MAWNO_").global().split('_'))
             .extract::<AppConfig>()?;
 
+        let mut names = HashSet::new();
+        for draw in config.draws() {
+            if !names.insert(&draw.name) {
+                bail!("multiple random draws are named `{}`", draw.name);
+            }
+        }
+
         Ok(config)
     }
 
+    /// Every random draw in the config, including the one in the
+    /// `random_draw` section
+    pub fn draws(&self) -> impl Iterator<Item = &RandomDrawConfig> {
+        self.random_draw.iter().chain(&self.random_draws)
+    }
+
+    /// Get a random draw from the config by name
+    pub fn draw(&self, name: &str) -> Option<&RandomDrawConfig> {
+        self.draws().find(|draw| draw.name == name)
+    }
+}
+
+impl MonologuesConfig {
     pub fn is_autosort_enabled(&self) -> bool {
-        self.monologues.category_id.is_some() && !self.monologues.disable_sorting
+        self.category_id.is_some() && !self.disable_sorting
     }
 }
@@ -1,149 +1,571 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use chrono::{DateTime, Utc};
 use color_eyre::eyre::{Result, WrapErr};
-use rand::{seq::SliceRandom, Rng};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    seq::SliceRandom,
+    Rng,
+};
 use serde::{Deserialize, Serialize};
-use serenity::all::{ChannelId, Context, GetMessages, Message, MessageId, MessageType};
+use serenity::all::{ChannelId, Context, GetMessages, GuildId, Message, MessageId, UserId};
 
-use crate::data::config::AppConfig;
+use crate::{
+    data::{config::DrawStrategy, storage::Storage},
+    filter::Filters,
+    ownership::Ownership,
+};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug)]
 pub struct Index {
-    #[serde(skip)]
-    index_file: PathBuf,
-    last_indexed: HashMap<ChannelId, MessageId>,
-    messages: HashMap<ChannelId, Vec<MessageId>>,
+    storage: Arc<dyn Storage>,
+    /// The indexed messages of each guild
+    guilds: HashMap<GuildId, GuildIndex>,
 }
 
-impl Index {
-    pub async fn load(config: &AppConfig) -> Result<Self> {
-        let index_file = config.state_dir.join("index.json");
-
-        if !index_file.exists() {
-            trace!("creating a new index file at {:?}", index_file);
-            return Ok(Self {
-                index_file,
-                last_indexed: HashMap::new(),
-                messages: HashMap::new(),
+/// A single change to the index.
+///
+/// Storage backends persist these individually so that recording a change
+/// does not require rewriting the entire index. Applying an event is
+/// idempotent so replaying events on top of a snapshot that already contains
+/// some of them is harmless.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum IndexEvent {
+    /// A message was added to the index. It becomes the last indexed message
+    /// in its channel if it is newer than the current one
+    Add {
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        meta: Option<MessageMeta>,
+    },
+    /// The metadata of an already indexed message changed
+    Update {
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        meta: MessageMeta,
+    },
+    Remove {
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    },
+    RemoveChannel {
+        guild_id: GuildId,
+        channel_id: ChannelId,
+    },
+    LastIndexed {
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    },
+}
+
+impl IndexEvent {
+    /// Apply this event to an in-memory index.
+    pub fn apply(self, guilds: &mut HashMap<GuildId, GuildIndex>) {
+        match self {
+            IndexEvent::Add {
+                guild_id,
+                channel_id,
+                message_id,
+                meta,
+            } => {
+                let guild = guilds.entry(guild_id).or_default();
+                let messages = guild.messages.entry(channel_id).or_default();
+
+                // messages are kept sorted by ID, which older messages added
+                // after the fact must not break
+                match messages.binary_search_by_key(&message_id, |m| m.id) {
+                    Ok(i) => {
+                        if meta.is_some() {
+                            messages[i].meta = meta;
+                        }
+                    }
+                    Err(i) => messages.insert(
+                        i,
+                        IndexedMessage {
+                            id: message_id,
+                            meta,
+                        },
+                    ),
+                }
+
+                // an older message can be added after the fact, e.g. when it
+                // is edited to become valid, which must not move the indexing
+                // position backwards
+                if guild
+                    .last_indexed(&channel_id)
+                    .is_none_or(|last| last < message_id)
+                {
+                    guild.just_indexed_inner(channel_id, message_id);
+                }
+            }
+            IndexEvent::Update {
+                guild_id,
+                channel_id,
+                message_id,
+                meta,
+            } => {
+                let existing = guilds
+                    .get_mut(&guild_id)
+                    .and_then(|guild| guild.messages.get_mut(&channel_id))
+                    .and_then(|messages| messages.iter_mut().find(|m| m.id == message_id));
+
+                if let Some(existing) = existing {
+                    existing.meta = Some(meta);
+                }
+            }
+            IndexEvent::Remove {
+                guild_id,
+                channel_id,
+                message_id,
+            } => {
+                if let Some(guild) = guilds.get_mut(&guild_id) {
+                    guild.remove_message(channel_id, message_id);
+                }
+            }
+            IndexEvent::RemoveChannel {
+                guild_id,
+                channel_id,
+            } => {
+                if let Some(guild) = guilds.get_mut(&guild_id) {
+                    guild.messages.remove(&channel_id);
+                    guild.last_indexed.remove(&channel_id);
+                }
+            }
+            IndexEvent::LastIndexed {
+                guild_id,
+                channel_id,
+                message_id,
+            } => {
+                guilds
+                    .entry(guild_id)
+                    .or_default()
+                    .just_indexed_inner(channel_id, message_id);
+            }
+        }
+    }
+}
+
+/// The indexed messages of a single guild.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GuildIndex {
+    pub(crate) last_indexed: HashMap<ChannelId, MessageId>,
+    pub(crate) messages: HashMap<ChannelId, Vec<IndexedMessage>>,
+}
+
+/// Which user's pool each message counts toward. Messages count toward the
+/// owner of their channel, except for the posts of collaborators when they
+/// count toward their own pool. See
+/// [`State::pools`](crate::data::state::State::pools).
+#[derive(Debug, Clone, Default)]
+pub struct Pools {
+    pub owners: HashMap<ChannelId, UserId>,
+    /// The collaborators whose posts count toward their own pool, by channel
+    pub collaborators: HashMap<ChannelId, HashSet<UserId>>,
+}
+
+impl Pools {
+    /// Get the user whose pool a message counts toward. Messages without
+    /// metadata count toward the owner of their channel
+    pub fn pool_of(&self, channel_id: ChannelId, message: &IndexedMessage) -> Option<UserId> {
+        let collaborator = message
+            .meta
+            .as_ref()
+            .map(|meta| meta.author_id)
+            .filter(|author_id| {
+                self.collaborators
+                    .get(&channel_id)
+                    .is_some_and(|collaborators| collaborators.contains(author_id))
             });
+
+        collaborator.or_else(|| self.owners.get(&channel_id).copied())
+    }
+
+    /// Check if `user_id` has a pool of messages at all, i.e. owns a channel
+    /// or collaborates on one
+    pub fn has_pool(&self, user_id: UserId) -> bool {
+        self.owners.values().any(|&owner| owner == user_id)
+            || self
+                .collaborators
+                .values()
+                .any(|collaborators| collaborators.contains(&user_id))
+    }
+}
+
+/// Pools and messages that are skipped when drawing a message, because their
+/// authors don't want them to be drawn. See
+/// [`State::exclusions`](crate::data::state::State::exclusions).
+#[derive(Debug, Clone, Default)]
+pub struct Exclusions {
+    /// The users whose whole pool is skipped
+    pub users: HashSet<UserId>,
+    pub messages: HashSet<MessageId>,
+    pub pools: Pools,
+}
+
+impl Exclusions {
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty() && self.messages.is_empty()
+    }
+
+    /// Check if a message may be drawn
+    pub fn allows(&self, channel_id: ChannelId, message: &IndexedMessage) -> bool {
+        !self.messages.contains(&message.id)
+            && self
+                .pools
+                .pool_of(channel_id, message)
+                .is_none_or(|user_id| !self.users.contains(&user_id))
+    }
+}
+
+/// A summary of the changes made while reconciling the index with Discord.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReconcileReport {
+    pub channels: usize,
+    pub added: usize,
+    pub removed: usize,
+    pub updated: usize,
+    /// The number of channels whose last indexed message no longer existed
+    pub repaired_last_indexed: usize,
+}
+
+impl ReconcileReport {
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.removed == 0 && self.updated == 0 && self.repaired_last_indexed == 0
+    }
+}
+
+impl std::ops::AddAssign for ReconcileReport {
+    fn add_assign(&mut self, other: Self) {
+        self.channels += other.channels;
+        self.added += other.added;
+        self.removed += other.removed;
+        self.updated += other.updated;
+        self.repaired_last_indexed += other.repaired_last_indexed;
+    }
+}
+
+impl std::fmt::Display for ReconcileReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "checked {} channels: {} messages added, {} removed, {} updated, {} channels repaired",
+            self.channels, self.added, self.removed, self.updated, self.repaired_last_indexed
+        )
+    }
+}
+
+/// A single message in the index.
+///
+/// Messages indexed before metadata was recorded are stored as a bare ID and
+/// have no metadata until they are indexed again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "IndexedMessageRepr")]
+pub struct IndexedMessage {
+    pub id: MessageId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<MessageMeta>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IndexedMessageRepr {
+    Bare(MessageId),
+    Full {
+        id: MessageId,
+        #[serde(default)]
+        meta: Option<MessageMeta>,
+    },
+}
+
+impl From<IndexedMessageRepr> for IndexedMessage {
+    fn from(repr: IndexedMessageRepr) -> Self {
+        match repr {
+            IndexedMessageRepr::Bare(id) => IndexedMessage { id, meta: None },
+            IndexedMessageRepr::Full { id, meta } => IndexedMessage { id, meta },
         }
+    }
+}
 
-        let index = tokio::fs::read_to_string(&index_file)
-            .await
-            .wrap_err("failed to read index file")?;
+impl From<&Message> for IndexedMessage {
+    fn from(message: &Message) -> Self {
+        IndexedMessage {
+            id: message.id,
+            meta: Some(MessageMeta::from(message)),
+        }
+    }
+}
 
-        let mut index =
-            serde_json::from_str::<Self>(&index).wrap_err("failed to parse index file")?;
+/// Information about a message that is needed to filter draws without fetching
+/// the message from Discord.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageMeta {
+    pub author_id: UserId,
+    /// The length of the message content in characters
+    pub content_len: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub attachment_count: u16,
+    /// The distinct content types of the attachments, if Discord provided them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachment_types: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub has_embeds: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub has_stickers: bool,
+    /// The message this message is a reply to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<MessageId>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub edited: bool,
+}
 
-        index.index_file = index_file;
+fn is_zero(n: &u16) -> bool {
+    *n == 0
+}
+
+impl From<&Message> for MessageMeta {
+    fn from(message: &Message) -> Self {
+        let mut attachment_types = message
+            .attachments
+            .iter()
+            .filter_map(|a| a.content_type.clone())
+            .collect::<Vec<_>>();
+        attachment_types.sort_unstable();
+        attachment_types.dedup();
 
-        Ok(index)
+        MessageMeta {
+            author_id: message.author.id,
+            content_len: message.content.chars().count() as u32,
+            attachment_count: message.attachments.len() as u16,
+            attachment_types,
+            has_embeds: !message.embeds.is_empty(),
+            has_stickers: !message.sticker_items.is_empty(),
+            reply_to: message
+                .message_reference
+                .as_ref()
+                .and_then(|r| r.message_id),
+            edited: message.edited_timestamp.is_some(),
+        }
     }
+}
+
+impl Index {
+    pub async fn load(storage: Arc<dyn Storage>) -> Result<Self> {
+        let mut guilds = storage
+            .load_index()
+            .await
+            .wrap_err("failed to load index")?
+            .unwrap_or_else(|| {
+                trace!("creating a new index");
+                HashMap::new()
+            });
 
+        // indexes saved by older versions weren't kept sorted and may contain
+        // duplicates
+        for messages in guilds
+            .values_mut()
+            .flat_map(|guild| guild.messages.values_mut())
+        {
+            messages.sort_unstable_by_key(|m| m.id);
+            messages.dedup_by_key(|m| m.id);
+        }
+
+        Ok(Self { storage, guilds })
+    }
+
+    /// Write a full snapshot of the index.
     async fn save(&self) -> Result<()> {
         trace!("saving index");
 
-        let index_json = serde_json::to_string(self).wrap_err("failed to serialize index")?;
-
-        tokio::fs::write(&self.index_file, index_json)
+        self.storage
+            .save_index(&self.guilds)
             .await
-            .wrap_err("failed to write serialized index")?;
+            .wrap_err("failed to save index")
+    }
 
-        Ok(())
+    /// Apply an event and persist it.
+    ///
+    /// This keeps the cost of persisting a single change independent of the
+    /// size of the index.
+    async fn record(&mut self, event: IndexEvent) -> Result<()> {
+        event.clone().apply(&mut self.guilds);
+
+        self.storage
+            .record_index_event(&event, &self.guilds)
+            .await
+            .wrap_err("failed to record index event")
     }
 
-    pub fn last_indexed(&self, channel_id: &ChannelId) -> Option<MessageId> {
-        self.last_indexed.get(channel_id).copied()
+    pub fn last_indexed(&self, guild_id: GuildId, channel_id: &ChannelId) -> Option<MessageId> {
+        self.guilds.get(&guild_id)?.last_indexed(channel_id)
     }
 
-    fn just_indexed_inner(&mut self, channel_id: ChannelId, message_id: MessageId) {
-        self.last_indexed.insert(channel_id, message_id);
+    fn guild_mut(&mut self, guild_id: GuildId) -> &mut GuildIndex {
+        self.guilds.entry(guild_id).or_default()
     }
 
     pub async fn just_indexed(
         &mut self,
+        guild_id: GuildId,
         channel_id: ChannelId,
         message_id: MessageId,
     ) -> Result<()> {
-        self.just_indexed_inner(channel_id, message_id);
-
-        self.save().await?;
-
-        Ok(())
+        self.record(IndexEvent::LastIndexed {
+            guild_id,
+            channel_id,
+            message_id,
+        })
+        .await
     }
 
-    fn is_message_valid(message: &Message) -> bool {
-        !message.author.bot
-            // && message.poll.is_none() need to wait for serenity 0.12.3 with
-            // fix for #2892
-            && matches!(
-                message.kind,
-                MessageType::Regular | MessageType::InlineReply
-            )
-    }
-
-    /// Precondition: All messages must be from the same channel.
-    ///
-    /// Caller is responsible for ensuring `last_indexed` is updated correctly.
-    fn extend_messages(&mut self, channel_id: ChannelId, messages: impl Iterator<Item = Message>) {
-        self.messages
-            .entry(channel_id)
-            .or_default()
-            .extend(messages.filter(Self::is_message_valid).map(|msg| msg.id));
+    /// Check if a message is eligible for the index under the given filters
+    pub(crate) fn is_message_valid(message: &Message, filters: &Filters) -> bool {
+        filters.allows(message, Utc::now())
     }
 
     /// Save a message to the index if it is valid.
-    pub async fn save_message(&mut self, message: &Message) -> Result<()> {
-        if !Self::is_message_valid(message) {
+    pub async fn save_message(
+        &mut self,
+        guild_id: GuildId,
+        message: &Message,
+        filters: &Filters,
+    ) -> Result<()> {
+        if !Self::is_message_valid(message, filters) {
             return Ok(());
         }
 
-        self.messages
-            .entry(message.channel_id)
-            .or_default()
-            .push(message.id);
+        self.record(IndexEvent::Add {
+            guild_id,
+            channel_id: message.channel_id,
+            message_id: message.id,
+            meta: Some(MessageMeta::from(message)),
+        })
+        .await
+    }
+
+    /// Bring the index up to date with the current contents of a message,
+    /// e.g. after it has been edited.
+    ///
+    /// The message is validated again: valid messages are added to the index or
+    /// have their metadata refreshed, and invalid messages are removed from it.
+    pub async fn refresh_message(
+        &mut self,
+        guild_id: GuildId,
+        message: &Message,
+        filters: &Filters,
+    ) -> Result<()> {
+        let indexed = self.get_message(guild_id, message.channel_id, message.id);
+        let valid = Self::is_message_valid(message, filters);
 
-        self.just_indexed_inner(message.channel_id, message.id);
+        let event = match indexed {
+            Some(_) if !valid => {
+                trace!(?message.id, "edited message is no longer valid");
+                IndexEvent::Remove {
+                    guild_id,
+                    channel_id: message.channel_id,
+                    message_id: message.id,
+                }
+            }
+            Some(indexed) => {
+                let meta = MessageMeta::from(message);
 
-        self.save().await?;
+                if indexed.meta.as_ref() == Some(&meta) {
+                    return Ok(());
+                }
 
-        Ok(())
+                IndexEvent::Update {
+                    guild_id,
+                    channel_id: message.channel_id,
+                    message_id: message.id,
+                    meta,
+                }
+            }
+            None if valid => {
+                trace!(?message.id, "edited message is now valid");
+                IndexEvent::Add {
+                    guild_id,
+                    channel_id: message.channel_id,
+                    message_id: message.id,
+                    meta: Some(MessageMeta::from(message)),
+                }
+            }
+            None => return Ok(()),
+        };
+
+        self.record(event).await
+    }
+
+    /// Check whether a message is in the index
+    pub fn contains(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> bool {
+        self.get_message(guild_id, channel_id, message_id).is_some()
+    }
+
+    fn get_message(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> Option<&IndexedMessage> {
+        self.guilds
+            .get(&guild_id)?
+            .messages
+            .get(&channel_id)?
+            .iter()
+            .find(|m| m.id == message_id)
     }
 
-    pub fn get_messages(&self, channel_id: &ChannelId) -> Option<Vec<MessageId>> {
-        self.messages.get(channel_id).cloned()
+    /// Get the stored metadata of a message, if it is indexed and has any
+    pub fn message_meta(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> Option<&MessageMeta> {
+        self.get_message(guild_id, channel_id, message_id)?
+            .meta
+            .as_ref()
+    }
+
+    pub fn get_messages(
+        &self,
+        guild_id: GuildId,
+        channel_id: &ChannelId,
+    ) -> Option<Vec<MessageId>> {
+        Some(
+            self.guilds
+                .get(&guild_id)?
+                .messages
+                .get(channel_id)?
+                .iter()
+                .map(|m| m.id)
+                .collect(),
+        )
     }
 
-    /// Randomly draw a message from all indexed messages.
+    /// Randomly draw a message from all indexed messages in the given guild.
     ///
     /// This deliberately does not maintain an equal distribution between
     /// channels; if one channel has many more messages than another, it will be
     /// more likely to be selected.
-    pub fn random_message(&self) -> Option<(ChannelId, MessageId)> {
-        // this works by treating all messages as a single list and picking a
-        // random index into that quasi-list. we then iterate over each
-        // sub-list, subtracting its length from the index until the index falls
-        // within a sub-list
-
-        let total_count = self.messages.values().map(|v| v.len()).sum::<usize>();
-
-        if total_count == 0 {
-            return None;
-        }
-
-        let mut random_index = rand::thread_rng().gen_range(0..total_count);
-
-        for (key, vec) in &self.messages {
-            if random_index < vec.len() {
-                return Some((*key, vec[random_index]));
-            } else {
-                random_index -= vec.len();
-            }
-        }
-
-        // not possible
-        None
+    pub fn random_message(
+        &self,
+        guild_id: GuildId,
+        exclusions: &Exclusions,
+    ) -> Option<(ChannelId, MessageId)> {
+        self.guilds.get(&guild_id)?.random_message(exclusions)
     }
 
     /// Randomly draw a message from all indexed messages in the given channel.
@@ -153,94 +575,253 @@ impl Index {
     // NOTE TO PROGRAMMER: there is no point in taking a reference to any ID
     // types because they're all `NonZeroU64`s which are the same size as a
     // reference
-    pub fn random_message_from(&self, channel_id: ChannelId) -> Option<MessageId> {
-        let messages = self.messages.get(&channel_id)?;
+    pub fn random_message_from(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        exclusions: &Exclusions,
+    ) -> Option<MessageId> {
+        let messages = self.guilds.get(&guild_id)?.messages.get(&channel_id)?;
 
-        messages.choose(&mut rand::thread_rng()).copied()
+        let messages = messages
+            .iter()
+            .filter(|m| exclusions.allows(channel_id, m))
+            .collect::<Vec<_>>();
+
+        messages.choose(&mut rand::thread_rng()).map(|m| m.id)
     }
 
-    /// Randomly draw a message from all indexed messages that were created
-    /// after the given timestamp.
+    /// Randomly draw a message from all indexed messages in the given guild
+    /// that were created after the given timestamp.
     ///
     /// This deliberately does not maintain an equal distribution between
     /// channels; if one channel has many more messages than another, it will be
     /// more likely to be selected.
-    pub fn random_message_since(&self, timestamp: DateTime<Utc>) -> Option<(ChannelId, MessageId)> {
-        // we need to maintain the association between channel and message id so
-        // we can't just flatten the hashmap. instead we flat_map each
-        // `(channel, message_ids)` pair into an iterator of `(channel,
-        // message_id)` pairs. since Discord message ids contain the timestamp,
-        // we do the filtering at the same time as mapping to avoid iterating
-        // over the entire list of messages twice
-        let all_messages: Vec<_> = self
-            .messages
-            .iter()
-            .flat_map(|(channel_id, message_ids)| {
-                message_ids.iter().filter_map(|&message_id| {
-                    if *message_id.created_at() >= timestamp {
-                        Some((*channel_id, message_id))
-                    } else {
-                        None
-                    }
-                })
-            })
-            .collect();
+    pub fn random_message_since(
+        &self,
+        guild_id: GuildId,
+        timestamp: DateTime<Utc>,
+        exclusions: &Exclusions,
+    ) -> Option<(ChannelId, MessageId)> {
+        self.random_message_matching(guild_id, exclusions, |_, message| {
+            *message.id.created_at() >= timestamp
+        })
+    }
 
-        all_messages.choose(&mut rand::thread_rng()).copied()
+    /// Randomly draw a message from all indexed messages in the given guild
+    /// that match the provided filter.
+    ///
+    /// The filter has access to the stored [`MessageMeta`], so it can be used
+    /// to narrow down the draw without fetching any messages. Like
+    /// [`Index::random_message`], each message is equally likely to be drawn.
+    pub fn random_message_matching(
+        &self,
+        guild_id: GuildId,
+        exclusions: &Exclusions,
+        filter: impl Fn(ChannelId, &IndexedMessage) -> bool,
+    ) -> Option<(ChannelId, MessageId)> {
+        self.guilds
+            .get(&guild_id)?
+            .random_message_matching(exclusions, filter)
+    }
+
+    /// Randomly draw a message that matches the provided filter from the given
+    /// guild, weighting each message according to `strategy`.
+    ///
+    /// `half_life_days` is only used by [`DrawStrategy::Recency`].
+    pub fn random_message_weighted(
+        &self,
+        guild_id: GuildId,
+        strategy: DrawStrategy,
+        half_life_days: f64,
+        exclusions: &Exclusions,
+        filter: impl Fn(ChannelId, &IndexedMessage) -> bool,
+    ) -> Option<(ChannelId, MessageId)> {
+        self.guilds.get(&guild_id)?.random_message_weighted(
+            strategy,
+            half_life_days,
+            exclusions,
+            filter,
+        )
     }
 
     /// Remove a message from the index.
     pub async fn remove_message(
         &mut self,
+        guild_id: GuildId,
         channel_id: ChannelId,
         message_id: MessageId,
     ) -> Result<()> {
-        let Some(messages) = self.messages.get_mut(&channel_id) else {
+        let Some(guild) = self.guilds.get(&guild_id) else {
             return Ok(());
         };
 
-        // remove the message
-        messages.retain(|&id| id != message_id);
+        if !guild.messages.contains_key(&channel_id) {
+            return Ok(());
+        }
 
-        // if the message was the last indexed message, we need to replace it
-        // with the new latest message (or delete it if that was the only msg)
-        if self.last_indexed(&channel_id) == Some(message_id) {
-            // SAFETY: we know this must exist, we just can't use the `messages`
-            // borrow because we need an immutable borrow for `last_indexed`
-            match self.messages.get_mut(&channel_id).unwrap().last().copied() {
-                Some(msg) => {
-                    self.just_indexed_inner(channel_id, msg);
-                }
+        self.record(IndexEvent::Remove {
+            guild_id,
+            channel_id,
+            message_id,
+        })
+        .await
+    }
+
+    /// Remove an entire channel from the index.
+    ///
+    /// Caller is responsible for removing it from the state as well.
+    pub async fn remove_channel(&mut self, guild_id: GuildId, channel_id: ChannelId) -> Result<()> {
+        if !self.guilds.contains_key(&guild_id) {
+            return Ok(());
+        }
+
+        self.record(IndexEvent::RemoveChannel {
+            guild_id,
+            channel_id,
+        })
+        .await
+    }
+
+    /// Bring a channel's index in line with a fresh, complete listing of the
+    /// valid messages in that channel.
+    ///
+    /// Indexed messages that no longer exist are removed, missing messages are
+    /// added and stale metadata is refreshed. `fetched_at` is the time the
+    /// listing started; messages created after it may be missing from the
+    /// listing because they were sent while fetching, so they are never
+    /// removed.
+    ///
+    /// The last indexed message of the channel is reset to the newest message
+    /// in the listing, which recovers from the previous one being deleted.
+    pub async fn reconcile_channel(
+        &mut self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        mut fetched: Vec<IndexedMessage>,
+        fetched_at: DateTime<Utc>,
+    ) -> Result<ReconcileReport> {
+        let mut report = ReconcileReport {
+            channels: 1,
+            ..Default::default()
+        };
+
+        let indexed = self
+            .guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.messages.get(&channel_id))
+            .map(|messages| {
+                messages
+                    .iter()
+                    .map(|m| (m.id, m.meta.clone()))
+                    .collect::<HashMap<_, _>>()
+            })
+            .unwrap_or_default();
+
+        // messages are listed newest first, but added in order so the index
+        // stays sorted
+        fetched.sort_unstable_by_key(|m| m.id);
+
+        let fetched_ids = fetched.iter().map(|m| m.id).collect::<HashSet<_>>();
+        let newest = fetched.iter().map(|m| m.id).max();
+
+        let mut events = Vec::new();
+
+        for &message_id in indexed.keys() {
+            if !fetched_ids.contains(&message_id) && *message_id.created_at() < fetched_at {
+                report.removed += 1;
+                events.push(IndexEvent::Remove {
+                    guild_id,
+                    channel_id,
+                    message_id,
+                });
+            }
+        }
+
+        for message in fetched {
+            match indexed.get(&message.id) {
                 None => {
-                    self.last_indexed.remove(&channel_id);
+                    report.added += 1;
+                    events.push(IndexEvent::Add {
+                        guild_id,
+                        channel_id,
+                        message_id: message.id,
+                        meta: message.meta,
+                    });
                 }
+                Some(meta) if *meta != message.meta => {
+                    if let Some(meta) = message.meta {
+                        report.updated += 1;
+                        events.push(IndexEvent::Update {
+                            guild_id,
+                            channel_id,
+                            message_id: message.id,
+                            meta,
+                        });
+                    }
+                }
+                Some(_) => {}
             }
         }
 
-        self.save().await?;
+        for event in events {
+            self.record(event).await?;
+        }
 
-        Ok(())
-    }
+        // removing messages already moves the last indexed message back to the
+        // newest remaining one, but only if the removed message was in the
+        // index to begin with. the last indexed message can also be one that
+        // was never indexed, like a bot message, which may have been deleted.
+        // anything sent after the listing started is left alone
+        let last_indexed = self.last_indexed(guild_id, &channel_id);
+        if let Some(newest) = newest {
+            let is_stale = |last: MessageId| {
+                last != newest && !fetched_ids.contains(&last) && *last.created_at() < fetched_at
+            };
 
-    /// Remove an entire channel from the index.
-    ///
-    /// Caller is responsible for removing it from the state as well.
-    pub async fn remove_channel(&mut self, channel_id: ChannelId) -> Result<()> {
-        self.messages.remove(&channel_id);
-        self.last_indexed.remove(&channel_id);
+            if last_indexed.is_none_or(is_stale) {
+                trace!(
+                    ?channel_id,
+                    ?last_indexed,
+                    ?newest,
+                    "repairing last indexed message"
+                );
+                report.repaired_last_indexed += 1;
 
-        self.save().await?;
+                self.record(IndexEvent::LastIndexed {
+                    guild_id,
+                    channel_id,
+                    message_id: newest,
+                })
+                .await?;
+            }
+        }
 
-        Ok(())
+        Ok(report)
     }
 
     /// Index all messages in the given channels.
     ///
-    ///
-    pub async fn index(&mut self, ctx: &Context, channels: Vec<ChannelId>) -> Result<()> {
-        for channel_id in channels {
-            debug!("indexing channel {:?}", channel_id);
-            let mut current_message = self.last_indexed(&channel_id);
+    /// Each channel is paired with the guild it belongs to. Messages are only
+    /// indexed if they pass the filters of their guild and aren't foreign
+    /// posts. Guilds without filters only use the base rules, and guilds
+    /// without ownership rules don't enforce ownership.
+    pub async fn index(
+        &mut self,
+        ctx: &Context,
+        channels: Vec<(GuildId, ChannelId)>,
+        filters: &HashMap<GuildId, Filters>,
+        ownership: &HashMap<GuildId, Ownership>,
+    ) -> Result<()> {
+        let no_filters = Filters::default();
+        let no_ownership = Ownership::default();
+
+        for (guild_id, channel_id) in channels {
+            debug!("indexing channel {:?} in guild {:?}", channel_id, guild_id);
+            let filters = filters.get(&guild_id).unwrap_or(&no_filters);
+            let ownership = ownership.get(&guild_id).unwrap_or(&no_ownership);
+            let guild = self.guild_mut(guild_id);
+            let mut current_message = guild.last_indexed(&channel_id);
             let mut latest_message = None;
             // if we already have a current message, we should go forward in
             // time
@@ -298,13 +879,13 @@ impl Index {
                 };
                 trace!(?next_message_id);
 
-                self.extend_messages(channel_id, messages.into_iter());
+                guild.extend_messages(channel_id, messages.into_iter(), filters, ownership);
 
                 current_message = next_message_id;
             }
 
             if let Some(latest_message) = latest_message {
-                self.just_indexed_inner(channel_id, latest_message);
+                guild.just_indexed_inner(channel_id, latest_message);
             }
         }
 
@@ -313,3 +894,363 @@ impl Index {
         Ok(())
     }
 }
+
+impl GuildIndex {
+    fn last_indexed(&self, channel_id: &ChannelId) -> Option<MessageId> {
+        self.last_indexed.get(channel_id).copied()
+    }
+
+    fn just_indexed_inner(&mut self, channel_id: ChannelId, message_id: MessageId) {
+        self.last_indexed.insert(channel_id, message_id);
+    }
+
+    /// Precondition: All messages must be from the same channel.
+    ///
+    /// Caller is responsible for ensuring `last_indexed` is updated correctly.
+    fn extend_messages(
+        &mut self,
+        channel_id: ChannelId,
+        messages: impl Iterator<Item = Message>,
+        filters: &Filters,
+        ownership: &Ownership,
+    ) {
+        let indexed = self.messages.entry(channel_id).or_default();
+        let known = indexed.iter().map(|m| m.id).collect::<HashSet<_>>();
+
+        // an outdated last indexed message means fetching messages that are
+        // indexed already, which must not be drawn more often because of it
+        indexed.extend(
+            messages
+                .filter(|msg| {
+                    !known.contains(&msg.id)
+                        && Index::is_message_valid(msg, filters)
+                        && !ownership.is_foreign(msg)
+                })
+                .map(|msg| IndexedMessage::from(&msg)),
+        );
+
+        // messages are listed newest first
+        indexed.sort_unstable_by_key(|m| m.id);
+    }
+
+    fn random_message(&self, exclusions: &Exclusions) -> Option<(ChannelId, MessageId)> {
+        if !exclusions.is_empty() {
+            return self.random_message_matching(exclusions, |_, _| true);
+        }
+
+        // this works by treating all messages as a single list and picking a
+        // random index into that quasi-list. we then iterate over each
+        // sub-list, subtracting its length from the index until the index falls
+        // within a sub-list
+
+        let total_count = self.messages.values().map(|v| v.len()).sum::<usize>();
+
+        if total_count == 0 {
+            return None;
+        }
+
+        let mut random_index = rand::thread_rng().gen_range(0..total_count);
+
+        for (key, vec) in &self.messages {
+            if random_index < vec.len() {
+                return Some((*key, vec[random_index].id));
+            } else {
+                random_index -= vec.len();
+            }
+        }
+
+        // not possible
+        None
+    }
+
+    fn random_message_matching(
+        &self,
+        exclusions: &Exclusions,
+        filter: impl Fn(ChannelId, &IndexedMessage) -> bool,
+    ) -> Option<(ChannelId, MessageId)> {
+        // we need to maintain the association between channel and message id so
+        // we can't just flatten the hashmap. instead we flat_map each
+        // `(channel, messages)` pair into an iterator of `(channel,
+        // message_id)` pairs. we do the filtering at the same time as mapping
+        // to avoid iterating over the entire list of messages twice
+        let filter = &filter;
+        let all_messages: Vec<_> = self
+            .messages
+            .iter()
+            .flat_map(|(&channel_id, messages)| {
+                messages.iter().filter_map(move |message| {
+                    if exclusions.allows(channel_id, message) && filter(channel_id, message) {
+                        Some((channel_id, message.id))
+                    } else {
+                        None
+                    }
+                })
+            })
+            .collect();
+
+        all_messages.choose(&mut rand::thread_rng()).copied()
+    }
+
+    fn random_message_weighted(
+        &self,
+        strategy: DrawStrategy,
+        half_life_days: f64,
+        exclusions: &Exclusions,
+        filter: impl Fn(ChannelId, &IndexedMessage) -> bool,
+    ) -> Option<(ChannelId, MessageId)> {
+        if strategy == DrawStrategy::Message {
+            return self.random_message_matching(exclusions, filter);
+        }
+
+        let now = Utc::now();
+        let half_life_days = half_life_days.max(f64::EPSILON);
+
+        let mut candidates = Vec::new();
+        let mut weights = Vec::new();
+
+        for (&channel_id, messages) in &self.messages {
+            let matching = messages
+                .iter()
+                .filter(|message| {
+                    exclusions.allows(channel_id, message) && filter(channel_id, message)
+                })
+                .collect::<Vec<_>>();
+            let count = matching.len() as f64;
+
+            for message in matching {
+                // each channel's total weight is the per-message weight times
+                // the number of messages in it, so `1 / count` gives every
+                // channel the same total and `1 / count²` gives each channel a
+                // total inversely proportional to its size
+                let weight = match strategy {
+                    DrawStrategy::Message => 1.0,
+                    DrawStrategy::Channel => 1.0 / count,
+                    DrawStrategy::InverseActivity => 1.0 / (count * count),
+                    DrawStrategy::Recency => {
+                        let age = now.signed_duration_since(*message.id.created_at());
+                        let age_days = age.num_seconds().max(0) as f64 / 86_400.0;
+
+                        // very old messages would otherwise round down to a
+                        // weight of zero, which `WeightedIndex` rejects if every
+                        // message is that old
+                        0.5f64
+                            .powf(age_days / half_life_days)
+                            .max(f64::MIN_POSITIVE)
+                    }
+                };
+
+                candidates.push((channel_id, message.id));
+                weights.push(weight);
+            }
+        }
+
+        // fails only if there are no candidates
+        let distribution = WeightedIndex::new(&weights).ok()?;
+
+        Some(candidates[distribution.sample(&mut rand::thread_rng())])
+    }
+
+    /// Remove a message from this guild's index.
+    fn remove_message(&mut self, channel_id: ChannelId, message_id: MessageId) {
+        let Some(messages) = self.messages.get_mut(&channel_id) else {
+            return;
+        };
+
+        // remove the message
+        messages.retain(|m| m.id != message_id);
+
+        // if the message was the last indexed message, we need to replace it
+        // with the new latest message (or delete it if that was the only msg)
+        if self.last_indexed(&channel_id) == Some(message_id) {
+            // SAFETY: we know this must exist, we just can't use the `messages`
+            // borrow because we need an immutable borrow for `last_indexed`
+            match self.messages.get(&channel_id).unwrap().last().map(|m| m.id) {
+                Some(msg) => {
+                    self.just_indexed_inner(channel_id, msg);
+                }
+                None => {
+                    self.last_indexed.remove(&channel_id);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    const DRAWS: usize = 20_000;
+
+    /// A message ID whose snowflake encodes `created_at`. `n` keeps IDs
+    /// created at the same time apart
+    fn message_at(created_at: DateTime<Utc>, n: u64) -> IndexedMessage {
+        const DISCORD_EPOCH_MS: i64 = 1_420_070_400_000;
+
+        let ms = (created_at.timestamp_millis() - DISCORD_EPOCH_MS) as u64;
+
+        IndexedMessage {
+            id: MessageId::new((ms << 22) | n),
+            meta: None,
+        }
+    }
+
+    /// A guild with a busy channel of nine messages and a quiet channel of
+    /// one, all posted at the same time
+    fn busy_and_quiet() -> (GuildIndex, ChannelId, ChannelId) {
+        let busy = ChannelId::new(1);
+        let quiet = ChannelId::new(2);
+        let now = Utc::now();
+
+        let mut guild = GuildIndex::default();
+        guild
+            .messages
+            .insert(busy, (1..=9).map(|n| message_at(now, n)).collect());
+        guild.messages.insert(quiet, vec![message_at(now, 10)]);
+
+        (guild, busy, quiet)
+    }
+
+    /// The share of `DRAWS` draws with `strategy` that picked a message from
+    /// `channel_id`
+    fn share_of(
+        guild: &GuildIndex,
+        strategy: DrawStrategy,
+        half_life_days: f64,
+        channel_id: ChannelId,
+    ) -> f64 {
+        let exclusions = Exclusions::default();
+
+        let hits = (0..DRAWS)
+            .filter(|_| {
+                let (drawn, _) = guild
+                    .random_message_weighted(strategy, half_life_days, &exclusions, |_, _| true)
+                    .unwrap();
+
+                drawn == channel_id
+            })
+            .count();
+
+        hits as f64 / DRAWS as f64
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        // the standard deviation of each share is below 0.004 at this many
+        // draws, so this only fails by chance about once in a billion runs
+        assert!(
+            (actual - expected).abs() < 0.025,
+            "expected a share of about {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn message_strategy_weights_every_message_equally() {
+        let (guild, _, quiet) = busy_and_quiet();
+
+        assert_close(share_of(&guild, DrawStrategy::Message, 30.0, quiet), 0.1);
+    }
+
+    #[test]
+    fn channel_strategy_weights_every_channel_equally() {
+        let (guild, _, quiet) = busy_and_quiet();
+
+        assert_close(share_of(&guild, DrawStrategy::Channel, 30.0, quiet), 0.5);
+    }
+
+    #[test]
+    fn inverse_activity_strategy_favors_quiet_channels() {
+        let (guild, _, quiet) = busy_and_quiet();
+
+        // the busy channel's total weight is 9 / 9², so 1/9 against 1
+        assert_close(
+            share_of(&guild, DrawStrategy::InverseActivity, 30.0, quiet),
+            0.9,
+        );
+    }
+
+    #[test]
+    fn recency_strategy_halves_the_weight_every_half_life() {
+        let new = ChannelId::new(1);
+        let old = ChannelId::new(2);
+        let now = Utc::now();
+
+        let mut guild = GuildIndex::default();
+        guild.messages.insert(new, vec![message_at(now, 1)]);
+        guild
+            .messages
+            .insert(old, vec![message_at(now - Duration::days(2), 2)]);
+
+        // two half lives old, so a quarter of the weight: 0.25 / 1.25
+        assert_close(share_of(&guild, DrawStrategy::Recency, 1.0, old), 0.2);
+    }
+
+    #[test]
+    fn strategies_respect_exclusions_and_filters() {
+        let (guild, busy, quiet) = busy_and_quiet();
+        let exclusions = Exclusions {
+            messages: guild.messages[&quiet].iter().map(|m| m.id).collect(),
+            ..Default::default()
+        };
+
+        for strategy in [
+            DrawStrategy::Message,
+            DrawStrategy::Channel,
+            DrawStrategy::InverseActivity,
+            DrawStrategy::Recency,
+        ] {
+            for _ in 0..100 {
+                let (drawn, _) = guild
+                    .random_message_weighted(strategy, 30.0, &exclusions, |_, _| true)
+                    .unwrap();
+                assert_eq!(drawn, busy);
+            }
+
+            let nothing =
+                guild.random_message_weighted(strategy, 30.0, &exclusions, |channel_id, _| {
+                    channel_id == quiet
+                });
+            assert_eq!(nothing, None);
+        }
+    }
+
+    /// An event adding a message to `channel_id` of guild 1
+    fn add(channel_id: ChannelId, message_id: u64) -> IndexEvent {
+        IndexEvent::Add {
+            guild_id: GuildId::new(1),
+            channel_id,
+            message_id: MessageId::new(message_id),
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn removing_the_newest_message_ignores_older_messages_added_later() {
+        let guild_id = GuildId::new(1);
+        let channel_id = ChannelId::new(1);
+        let mut guilds = HashMap::new();
+
+        add(channel_id, 10).apply(&mut guilds);
+        add(channel_id, 20).apply(&mut guilds);
+        // e.g. edited to become valid after the newer message was indexed
+        add(channel_id, 5).apply(&mut guilds);
+
+        IndexEvent::Remove {
+            guild_id,
+            channel_id,
+            message_id: MessageId::new(20),
+        }
+        .apply(&mut guilds);
+
+        let guild = &guilds[&guild_id];
+        assert_eq!(guild.last_indexed(&channel_id), Some(MessageId::new(10)));
+        assert_eq!(
+            guild.messages[&channel_id]
+                .iter()
+                .map(|m| m.id.get())
+                .collect::<Vec<_>>(),
+            [5, 10]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::{marker::PhantomData, path::PathBuf};
+
+use color_eyre::eyre::{Result, WrapErr};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::AsyncWriteExt;
+
+/// An append-only log of events stored as newline delimited JSON.
+///
+/// The journal is used alongside a snapshot of the data it modifies. Events
+/// are appended as they happen and replayed on top of the snapshot on startup.
+/// Once the journal grows large enough, the owner should write a new snapshot
+/// and [`clear`](Journal::clear) the journal.
+#[derive(Debug, Clone)]
+pub struct Journal<T> {
+    path: PathBuf,
+    /// The number of events in the journal
+    len: usize,
+    _event: PhantomData<fn(T)>,
+}
+
+impl<T: Serialize + DeserializeOwned> Journal<T> {
+    /// Create a handle to a journal without reading it
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            len: 0,
+            _event: PhantomData,
+        }
+    }
+
+    /// Open the journal at `path`, returning a handle and all events it
+    /// contains.
+    ///
+    /// A crash while appending can leave a partially written event at the end
+    /// of the journal. Such an event is discarded with a warning and removed
+    /// from the file; any other malformed event is an error.
+    pub async fn open(path: PathBuf) -> Result<(Self, Vec<T>)> {
+        let mut journal = Self::new(path);
+
+        if !journal.path.exists() {
+            return Ok((journal, Vec::new()));
+        }
+
+        let contents = tokio::fs::read_to_string(&journal.path)
+            .await
+            .wrap_err("failed to read journal")?;
+
+        let lines = contents.split_inclusive('\n').collect::<Vec<_>>();
+        let mut events = Vec::with_capacity(lines.len());
+        // the length of the journal up to the end of the last event read
+        let mut valid_len = 0;
+
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line.trim_end_matches('\n')) {
+                Ok(event) => {
+                    events.push(event);
+                    valid_len += line.len();
+                }
+                Err(err) if i == lines.len() - 1 => {
+                    warn!(
+                        "discarding partially written event at the end of {:?}: {}",
+                        journal.path, err
+                    );
+                }
+                Err(err) => {
+                    return Err(err).wrap_err(format!("failed to parse journal line {}", i + 1));
+                }
+            }
+        }
+
+        // events appended later must start on a line of their own, or the
+        // partial event would end up in the middle of the journal
+        if valid_len < contents.len() {
+            let file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .open(&journal.path)
+                .await
+                .wrap_err("failed to open journal")?;
+
+            file.set_len(valid_len as u64)
+                .await
+                .wrap_err("failed to truncate journal")?;
+
+            file.sync_data().await.wrap_err("failed to sync journal")?;
+        } else if !contents.is_empty() && !contents.ends_with('\n') {
+            let mut file = tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(&journal.path)
+                .await
+                .wrap_err("failed to open journal")?;
+
+            file.write_all(b"\n")
+                .await
+                .wrap_err("failed to append to journal")?;
+
+            file.sync_data().await.wrap_err("failed to sync journal")?;
+        }
+
+        journal.len = events.len();
+
+        Ok((journal, events))
+    }
+
+    /// The number of events appended since the journal was last cleared
+    pub fn event_count(&self) -> usize {
+        self.len
+    }
+
+    /// Append an event to the end of the journal.
+    pub async fn append(&mut self, event: &T) -> Result<()> {
+        let mut line = serde_json::to_vec(event).wrap_err("failed to serialize event")?;
+        line.push(b'\n');
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .wrap_err("failed to open journal")?;
+
+        file.write_all(&line)
+            .await
+            .wrap_err("failed to append to journal")?;
+
+        file.sync_data().await.wrap_err("failed to sync journal")?;
+
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Remove every event from the journal.
+    ///
+    /// Must only be called after the events have been persisted elsewhere.
+    pub async fn clear(&mut self) -> Result<()> {
+        if self.path.exists() {
+            tokio::fs::remove_file(&self.path)
+                .await
+                .wrap_err("failed to remove journal")?;
+        }
+
+        self.len = 0;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Result, WrapErr};
+use serde::Deserialize;
+use serenity::all::{Channel, ChannelId, GuildId, Http, MessageId, UserId};
+
+use crate::{
+    data::{
+        config::{AppConfig, DEFAULT_DRAW},
+        index::{GuildIndex, IndexedMessage},
+        persist,
+        state::GuildState,
+        storage::{json::JsonStorage, Storage},
+    },
+    utils,
+};
+
+/// The state file layout used before guilds were tracked separately.
+#[derive(Debug, Deserialize)]
+struct LegacyState {
+    channels: HashMap<UserId, ChannelId>,
+    last_trigger: Option<DateTime<Utc>>,
+    #[serde(default)]
+    channel_positions: HashMap<ChannelId, u16>,
+}
+
+/// The index file layout used before guilds were tracked separately.
+#[derive(Debug, Deserialize)]
+struct LegacyIndex {
+    last_indexed: HashMap<ChannelId, MessageId>,
+    messages: HashMap<ChannelId, Vec<MessageId>>,
+}
+
+/// Migrate flat (single guild) state and index files to the per-guild layout.
+///
+/// The old files did not record which guild a channel belonged to, so each
+/// channel is looked up through the Discord API. Channels which no longer exist
+/// are dropped, while any other failure to look one up aborts the migration so
+/// it is tried again on the next start. The original files are kept next to the new ones with a `.v1`
+/// suffix in case something goes wrong.
+///
+/// This always operates on the JSON files, even if another storage backend is
+/// configured. Other backends import the migrated JSON files afterwards.
+///
+/// This does nothing if the state file is missing or already migrated.
+pub async fn migrate_legacy(config: &AppConfig) -> Result<()> {
+    let state_file = config.state_dir.join("state.json");
+    let index_file = config.state_dir.join("index.json");
+
+    if !state_file.exists() {
+        return Ok(());
+    }
+
+    let state = tokio::fs::read_to_string(&state_file)
+        .await
+        .wrap_err("failed to read state file")?;
+
+    let state =
+        serde_json::from_str::<serde_json::Value>(&state).wrap_err("failed to parse state file")?;
+
+    if state.get("guilds").is_some() {
+        trace!("state file is already in the per-guild format");
+        return Ok(());
+    }
+
+    info!("migrating state and index to the per-guild format");
+
+    let legacy_state = serde_json::from_value::<LegacyState>(state)
+        .wrap_err("failed to parse legacy state file")?;
+
+    let legacy_index = persist::load_json::<LegacyIndex>(&index_file)
+        .await
+        .wrap_err("failed to load legacy index file")?;
+
+    let http = Http::new(&config.token);
+
+    // every channel we know about, in either file, needs to be resolved to its
+    // guild
+    let mut channel_ids = legacy_state.channels.values().copied().collect::<Vec<_>>();
+    if let Some(legacy_index) = &legacy_index {
+        channel_ids.extend(legacy_index.messages.keys().copied());
+    }
+    channel_ids.sort_unstable();
+    channel_ids.dedup();
+
+    let mut channel_guilds = HashMap::with_capacity(channel_ids.len());
+
+    for channel_id in channel_ids {
+        match http.get_channel(channel_id).await {
+            Ok(Channel::Guild(channel)) => {
+                channel_guilds.insert(channel_id, channel.guild_id);
+            }
+            Ok(_) => warn!(
+                ?channel_id,
+                "legacy channel is not a guild channel, dropping it"
+            ),
+            Err(err) if utils::is_not_found(&err) => {
+                warn!(?channel_id, "legacy channel no longer exists, dropping it")
+            }
+            // anything else may well succeed on the next start, which retries
+            // the whole migration since nothing was written yet
+            Err(err) => {
+                return Err(err)
+                    .wrap_err_with(|| format!("failed to resolve legacy channel {channel_id}"));
+            }
+        }
+    }
+
+    let mut guilds = HashMap::<GuildId, GuildState>::new();
+
+    for (user_id, channel_id) in legacy_state.channels {
+        let Some(&guild_id) = channel_guilds.get(&channel_id) else {
+            continue;
+        };
+
+        let guild = guilds.entry(guild_id).or_default();
+
+        guild.channels.insert(user_id, channel_id);
+
+        if let Some(&position) = legacy_state.channel_positions.get(&channel_id) {
+            guild.channel_positions.insert(channel_id, position);
+        }
+    }
+
+    // there was only one draw before, so every guild shares its last trigger
+    for guild in guilds.values_mut() {
+        if let Some(last_trigger) = legacy_state.last_trigger {
+            guild
+                .last_triggers
+                .insert(DEFAULT_DRAW.to_string(), last_trigger);
+        }
+    }
+
+    let mut guild_indexes = HashMap::<GuildId, GuildIndex>::new();
+
+    if let Some(legacy_index) = legacy_index {
+        for (channel_id, messages) in legacy_index.messages {
+            let Some(&guild_id) = channel_guilds.get(&channel_id) else {
+                continue;
+            };
+
+            let guild = guild_indexes.entry(guild_id).or_default();
+
+            if let Some(&last_indexed) = legacy_index.last_indexed.get(&channel_id) {
+                guild.last_indexed.insert(channel_id, last_indexed);
+            }
+
+            guild.messages.insert(
+                channel_id,
+                messages
+                    .into_iter()
+                    .map(|id| IndexedMessage { id, meta: None })
+                    .collect(),
+            );
+        }
+
+        if index_file.exists() {
+            tokio::fs::copy(&index_file, index_file.with_extension("json.v1"))
+                .await
+                .wrap_err("failed to back up legacy index file")?;
+        }
+    }
+
+    if state_file.exists() {
+        tokio::fs::copy(&state_file, state_file.with_extension("json.v1"))
+            .await
+            .wrap_err("failed to back up legacy state file")?;
+    }
+
+    info!(
+        "migrated {} channels into {} guilds",
+        channel_guilds.len(),
+        guilds.len()
+    );
+
+    let storage = JsonStorage::new(config);
+
+    storage.save_state(&guilds).await?;
+    storage.save_index(&guild_indexes).await?;
+
+    Ok(())
+}
@@ -1,11 +1,17 @@
 use std::sync::Arc;
 
 use color_eyre::eyre::{Report, Result};
-use tokio::sync::Mutex;
+use serenity::all::GuildId;
+use tokio::sync::{Mutex, Notify};
 
 pub mod config;
 pub mod index;
+pub mod journal;
+pub mod migrate;
+pub mod persist;
+pub mod settings;
 pub mod state;
+pub mod storage;
 
 /// The main data struct that contains the config, state, and index.
 #[derive(Debug)]
@@ -13,22 +19,37 @@ pub struct DataInner {
     pub config: config::AppConfig,
     pub state: Mutex<state::State>,
     pub index: Mutex<index::Index>,
+    /// Notified whenever a change is made that could affect the random draw
+    /// schedule of a guild, so the draw tasks can recompute their schedules
+    pub reschedule: Notify,
+}
+
+impl DataInner {
+    /// Get the effective config for a guild, taking its overrides into account
+    pub async fn guild_config(&self, guild_id: GuildId) -> Arc<settings::GuildConfig> {
+        self.state.lock().await.guild_config(&self.config, guild_id)
+    }
 }
 
 pub async fn load() -> Result<Data> {
     debug!("Loading configuration");
     let config = config::AppConfig::load()?;
 
+    migrate::migrate_legacy(&config).await?;
+
+    let storage = storage::open(&config).await?;
+
     debug!("Loading state");
-    let state = state::State::load(&config).await?;
+    let state = state::State::load(storage.clone()).await?;
 
     debug!("Loading index");
-    let index = index::Index::load(&config).await?;
+    let index = index::Index::load(storage).await?;
 
     let data = DataInner {
         config,
         state: Mutex::new(state),
         index: Mutex::new(index),
+        reschedule: Notify::new(),
     };
 
     Ok(Arc::new(data))
@@ -0,0 +1,116 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{bail, Result, WrapErr};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::AsyncWriteExt;
+
+/// Append `suffix` to the full file name of `path`, e.g. `state.json` becomes
+/// `state.json.bak`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// The location of the backup for the given file
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+/// Serialize `value` and atomically replace the file at `path` with it.
+///
+/// The data is first written and synced to a temporary file next to `path`.
+/// The current file is then rotated to its `.bak` path and the temporary file
+/// is renamed into place. Renames within a directory are atomic, so at every
+/// point in time either `path` or its backup holds a complete snapshot.
+pub async fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let json = serde_json::to_vec(value).wrap_err("failed to serialize")?;
+
+    write_atomic(path, &json).await
+}
+
+/// Atomically replace the file at `path` with `contents`.
+///
+/// See [`save_json`] for the guarantees this provides.
+pub async fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let tmp_path = with_suffix(path, ".tmp");
+
+    let mut file = tokio::fs::File::create(&tmp_path)
+        .await
+        .wrap_err("failed to create temporary file")?;
+
+    file.write_all(contents)
+        .await
+        .wrap_err("failed to write temporary file")?;
+
+    // make sure the data actually hit the disk before the rename makes it
+    // visible, otherwise a crash could leave an empty file behind
+    file.sync_all()
+        .await
+        .wrap_err("failed to sync temporary file")?;
+
+    drop(file);
+
+    if tokio::fs::try_exists(path).await.unwrap_or(false) {
+        tokio::fs::rename(path, backup_path(path))
+            .await
+            .wrap_err("failed to rotate backup")?;
+    }
+
+    tokio::fs::rename(&tmp_path, path)
+        .await
+        .wrap_err("failed to move temporary file into place")?;
+
+    Ok(())
+}
+
+async fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .wrap_err("failed to read file")?;
+
+    serde_json::from_str(&contents).wrap_err("failed to parse file")
+}
+
+/// Load and parse the file at `path`, falling back to its backup if the file
+/// is missing or fails to parse.
+///
+/// Returns `None` if neither the file nor its backup exist.
+pub async fn load_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    let backup = backup_path(path);
+
+    let primary_err = if path.exists() {
+        match read_json(path).await {
+            Ok(value) => return Ok(Some(value)),
+            Err(err) => Some(err),
+        }
+    } else {
+        None
+    };
+
+    if !backup.exists() {
+        return match primary_err {
+            Some(err) => Err(err.wrap_err(format!("failed to load {:?}", path))),
+            None => Ok(None),
+        };
+    }
+
+    match &primary_err {
+        Some(err) => error!(
+            "!!! failed to load {:?}, FALLING BACK TO BACKUP {:?}. changes made since the backup was taken are lost: {:?}",
+            path, backup, err
+        ),
+        None => warn!("{:?} is missing, falling back to backup {:?}", path, backup),
+    }
+
+    match read_json(&backup).await {
+        Ok(value) => Ok(Some(value)),
+        Err(err) => {
+            if let Some(primary_err) = primary_err {
+                error!("failed to load {:?}: {:?}", path, primary_err);
+            }
+
+            bail!("failed to load both {:?} and its backup: {:?}", path, err);
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::{BTreeSet, HashMap};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, RoleId};
+
+use crate::{
+    data::config::{
+        AppConfig, CatchUpPolicy, CollaboratorPosts, DrawMessage, DrawStrategy, Enforcement,
+        FallbackPolicy, MonologuesConfig, RandomDrawConfig, RawDrawMessage, RepostFormat, Schedule,
+        Timezone, DEFAULT_DRAW,
+    },
+    filter::{AttachmentFilter, Filters},
+};
+
+/// Per-guild overrides for the values in [`AppConfig`].
+///
+/// Every field is optional. Unset fields fall back to the value in the config
+/// file (or environment), which acts as the default for every guild.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GuildSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<ChannelId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_anyone: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disable_sorting: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enforcement: Option<Enforcement>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guest_role_id: Option<RoleId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_replies: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notify_foreign: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collaborator_posts: Option<CollaboratorPosts>,
+    /// Overrides for the default random draw
+    #[serde(default)]
+    pub random_draw: RandomDrawSettings,
+    /// Overrides for the named random draws, keyed by name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub draws: HashMap<String, RandomDrawSettings>,
+    /// Overrides for the eligibility filters
+    #[serde(default)]
+    pub filters: FilterSettings,
+}
+
+impl GuildSettings {
+    /// Get the overrides for the random draw with the given name
+    pub fn draw(&self, name: &str) -> Option<&RandomDrawSettings> {
+        if name == DEFAULT_DRAW {
+            Some(&self.random_draw)
+        } else {
+            self.draws.get(name)
+        }
+    }
+
+    /// Get the overrides for the random draw with the given name, creating
+    /// them if necessary
+    pub fn draw_mut(&mut self, name: &str) -> &mut RandomDrawSettings {
+        if name == DEFAULT_DRAW {
+            &mut self.random_draw
+        } else {
+            self.draws.entry(name.to_string()).or_default()
+        }
+    }
+}
+
+/// Per-guild overrides for the values in [`RandomDrawConfig`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RandomDrawSettings {
+    /// Explicitly enable or disable random draws in this guild
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<ChannelId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<Timezone>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Kept as written because they were saved before templates were
+    /// validated, see `parse_messages`
+    pub messages: Option<Vec<RawDrawMessage>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<DrawStrategy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub half_life_days: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_draws: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_hours: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_authors: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<FallbackPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_age_days: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_days: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_channels: Option<Vec<ChannelId>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catch_up: Option<CatchUpPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_this_day: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<RepostFormat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reupload_attachments: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reupload_limit_mb: Option<u32>,
+}
+
+/// Per-guild overrides for the values in [`Filters`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FilterSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachments: Option<AttachmentFilter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude_links_only: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude_spoilers: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude_polls: Option<bool>,
+    /// Kept as written, see `parse_patterns`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude_patterns: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_days: Option<u32>,
+}
+
+/// The effective configuration of a single guild, produced by layering the
+/// guild's [`GuildSettings`] on top of the [`AppConfig`].
+#[derive(Debug, Clone)]
+pub struct GuildConfig {
+    pub monologues: MonologuesConfig,
+    /// Every random draw that is enabled in this guild, sorted by name
+    pub draws: Vec<RandomDrawConfig>,
+    pub filters: Filters,
+}
+
+impl GuildConfig {
+    pub fn resolve(config: &AppConfig, settings: &GuildSettings) -> Self {
+        let defaults = &config.monologues;
+
+        let monologues = MonologuesConfig {
+            category_id: settings.category_id.or(defaults.category_id),
+            allow_anyone: settings.allow_anyone.unwrap_or(defaults.allow_anyone),
+            disable_sorting: settings.disable_sorting.unwrap_or(defaults.disable_sorting),
+            enforcement: settings.enforcement.unwrap_or(defaults.enforcement),
+            guest_role_id: settings.guest_role_id.or(defaults.guest_role_id),
+            allow_replies: settings.allow_replies.unwrap_or(defaults.allow_replies),
+            notify_foreign: settings.notify_foreign.unwrap_or(defaults.notify_foreign),
+            collaborator_posts: settings
+                .collaborator_posts
+                .unwrap_or(defaults.collaborator_posts),
+        };
+
+        // a guild can both override the draws from the config and define new
+        // ones of its own
+        let names = config
+            .draws()
+            .map(|draw| draw.name.as_str())
+            .chain(settings.draws.keys().map(String::as_str))
+            .chain([DEFAULT_DRAW])
+            .collect::<BTreeSet<_>>();
+
+        let no_overrides = RandomDrawSettings::default();
+
+        let draws = names
+            .into_iter()
+            .filter_map(|name| {
+                resolve_random_draw(
+                    name,
+                    config.draw(name),
+                    settings.draw(name).unwrap_or(&no_overrides),
+                )
+            })
+            .collect();
+
+        let filters = resolve_filters(&config.filters, &settings.filters);
+
+        Self {
+            monologues,
+            draws,
+            filters,
+        }
+    }
+
+    /// Get an enabled random draw by name
+    pub fn draw(&self, name: &str) -> Option<&RandomDrawConfig> {
+        self.draws.iter().find(|draw| draw.name == name)
+    }
+
+    pub fn is_autosort_enabled(&self) -> bool {
+        self.monologues.is_autosort_enabled()
+    }
+}
+
+fn resolve_random_draw(
+    name: &str,
+    defaults: Option<&RandomDrawConfig>,
+    settings: &RandomDrawSettings,
+) -> Option<RandomDrawConfig> {
+    if settings.enabled == Some(false) {
+        return None;
+    }
+
+    // the channel and schedule are required, so they must come from somewhere
+    let channel_id = settings
+        .channel_id
+        .or_else(|| defaults.map(|d| d.channel_id))?;
+    let schedule = settings
+        .schedule
+        .clone()
+        .or_else(|| defaults.map(|d| d.schedule.clone()))?;
+
+    Some(RandomDrawConfig {
+        name: name.to_string(),
+        channel_id,
+        schedule,
+        messages: match &settings.messages {
+            Some(messages) => parse_messages(messages),
+            None => defaults.map(|d| d.messages.clone()).unwrap_or_default(),
+        },
+        timezone: settings
+            .timezone
+            .or_else(|| defaults.map(|d| d.timezone))
+            .unwrap_or_default(),
+        strategy: settings
+            .strategy
+            .or_else(|| defaults.map(|d| d.strategy))
+            .unwrap_or_default(),
+        half_life_days: settings
+            .half_life_days
+            .or_else(|| defaults.map(|d| d.half_life_days))
+            .unwrap_or_else(RandomDrawConfig::default_half_life_days),
+        cooldown_draws: settings
+            .cooldown_draws
+            .or_else(|| defaults.map(|d| d.cooldown_draws))
+            .unwrap_or_default(),
+        cooldown_hours: settings
+            .cooldown_hours
+            .or_else(|| defaults.map(|d| d.cooldown_hours))
+            .unwrap_or_default(),
+        cooldown_authors: settings
+            .cooldown_authors
+            .or_else(|| defaults.map(|d| d.cooldown_authors))
+            .unwrap_or_default(),
+        fallback: settings
+            .fallback
+            .or_else(|| defaults.map(|d| d.fallback))
+            .unwrap_or_default(),
+        fallback_message: settings
+            .fallback_message
+            .clone()
+            .or_else(|| defaults.map(|d| d.fallback_message.clone()))
+            .unwrap_or_else(RandomDrawConfig::default_fallback_message),
+        min_age_days: settings
+            .min_age_days
+            .or_else(|| defaults.and_then(|d| d.min_age_days)),
+        max_age_days: settings
+            .max_age_days
+            .or_else(|| defaults.and_then(|d| d.max_age_days)),
+        source_channels: settings
+            .source_channels
+            .clone()
+            .or_else(|| defaults.map(|d| d.source_channels.clone()))
+            .unwrap_or_default(),
+        catch_up: settings
+            .catch_up
+            .or_else(|| defaults.map(|d| d.catch_up))
+            .unwrap_or_default(),
+        on_this_day: settings
+            .on_this_day
+            .or_else(|| defaults.map(|d| d.on_this_day))
+            .unwrap_or_default(),
+        format: settings
+            .format
+            .or_else(|| defaults.map(|d| d.format))
+            .unwrap_or_default(),
+        reupload_attachments: settings
+            .reupload_attachments
+            .or_else(|| defaults.map(|d| d.reupload_attachments))
+            .unwrap_or_default(),
+        reupload_limit_mb: settings
+            .reupload_limit_mb
+            .or_else(|| defaults.map(|d| d.reupload_limit_mb))
+            .unwrap_or_else(RandomDrawConfig::default_reupload_limit_mb),
+    })
+}
+
+fn resolve_filters(defaults: &Filters, settings: &FilterSettings) -> Filters {
+    Filters {
+        min_length: settings.min_length.unwrap_or(defaults.min_length),
+        attachments: settings.attachments.unwrap_or(defaults.attachments),
+        exclude_links_only: settings
+            .exclude_links_only
+            .unwrap_or(defaults.exclude_links_only),
+        exclude_spoilers: settings
+            .exclude_spoilers
+            .unwrap_or(defaults.exclude_spoilers),
+        exclude_polls: settings.exclude_polls.unwrap_or(defaults.exclude_polls),
+        exclude_patterns: match &settings.exclude_patterns {
+            Some(patterns) => parse_patterns(patterns),
+            None => defaults.exclude_patterns.clone(),
+        },
+        max_age_days: settings.max_age_days.or(defaults.max_age_days),
+    }
+}
+
+/// Parse the exclusion patterns of a guild, skipping any that are invalid.
+/// `/config set` rejects invalid patterns, but a pattern that a newer version
+/// of the regex engine rejects shouldn't keep the bot from starting.
+fn parse_patterns(patterns: &[String]) -> Vec<Regex> {
+    patterns
+        .iter()
+        .filter_map(|pattern| match Regex::new(pattern) {
+            Ok(regex) => Some(regex),
+            Err(err) => {
+                warn!("skipping invalid filter pattern: {:#}", err);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Parse the messages of a draw, skipping any that are invalid. `/config set`
+/// rejects invalid messages, but ones saved before templates were validated
+/// shouldn't keep the bot from starting.
+fn parse_messages(messages: &[RawDrawMessage]) -> Vec<DrawMessage> {
+    messages
+        .iter()
+        .filter_map(|message| match DrawMessage::try_from(message.clone()) {
+            Ok(message) => Some(message),
+            Err(err) => {
+                warn!("skipping invalid random draw message: {:#}", err);
+                None
+            }
+        })
+        .collect()
+}
@@ -1,86 +1,360 @@
-use std::{collections::HashMap, path::PathBuf};
-
-use chrono::{DateTime, Utc};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    str::FromStr,
+    sync::Arc,
+};
+
+use chrono::{DateTime, Duration, Utc};
 use color_eyre::eyre::{Context, Result};
 use serde::{Deserialize, Serialize};
-use serenity::all::{ChannelId, UserId};
-
-use crate::data::config::AppConfig;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+
+use crate::{
+    data::{
+        config::{AppConfig, CollaboratorPosts, MonologuesConfig, DEFAULT_DRAW},
+        index::{Exclusions, Pools},
+        settings::{GuildConfig, GuildSettings},
+        storage::Storage,
+    },
+    ownership::Ownership,
+};
+
+#[derive(Debug)]
 pub struct State {
-    #[serde(skip)]
-    state_file: PathBuf,
+    storage: Arc<dyn Storage>,
+    /// The state of each guild the bot is managing monologue channels in
+    guilds: HashMap<GuildId, GuildState>,
+    /// The effective config of each guild, resolved from its settings when
+    /// first needed and dropped whenever they change
+    configs: HashMap<GuildId, Arc<GuildConfig>>,
+}
+
+/// The state associated with a single guild.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GuildState {
     /// Mapping of user IDs to monologue channel IDs
-    channels: HashMap<UserId, ChannelId>,
-    /// Last trigger time for random draw
-    last_trigger: Option<DateTime<Utc>>,
+    pub(crate) channels: HashMap<UserId, ChannelId>,
+    /// Last trigger time of the default random draw, from before each draw
+    /// kept its own. Moved into `last_triggers` on load
+    #[serde(default, skip_serializing)]
+    pub(crate) last_trigger: Option<DateTime<Utc>>,
+    /// Last trigger time of each random draw, keyed by name
+    #[serde(default)]
+    pub(crate) last_triggers: HashMap<String, DateTime<Utc>>,
     /// A cache of the order of channels in the monologue category
     ///
     /// This is used to prevent fetching every channel in the category just to
     /// get their positions. The positions are used to order the channels based
     /// on which has been used most recently.
     #[serde(default)]
-    channel_positions: HashMap<ChannelId, u16>,
+    pub(crate) channel_positions: HashMap<ChannelId, u16>,
+    /// Guild specific overrides for the config
+    #[serde(default)]
+    pub(crate) settings: GuildSettings,
+    /// The messages reposted by recent random draws, oldest first
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    pub(crate) draws: VecDeque<DrawRecord>,
+    /// The number of messages each random draw has reposted, keyed by name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) draw_counts: HashMap<String, u64>,
+    /// The privacy choices of each user who made any
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) privacy: HashMap<UserId, Privacy>,
+    /// Messages that their authors excluded from draws, by channel
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) excluded_messages: HashMap<ChannelId, HashSet<MessageId>>,
+    /// Messages that were flagged as foreign posts, by channel. Unlike
+    /// `excluded_messages`, their authors can't include them again
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) flagged_messages: HashMap<ChannelId, HashSet<MessageId>>,
+    /// How often each message was reposted by any random draw. Unlike
+    /// `draws`, this is never trimmed
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) message_stats: HashMap<MessageId, MessageStats>,
+    /// Users other than the owner who may post in a monologue channel, by
+    /// channel
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) collaborators: HashMap<ChannelId, HashSet<UserId>>,
+    /// The outcome of the latest runs of each random draw, oldest first.
+    /// Unlike `draws`, this includes runs that didn't repost a message
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    pub(crate) draw_log: VecDeque<DrawLogEntry>,
 }
 
-impl State {
-    pub async fn load(config: &AppConfig) -> Result<Self> {
-        let state_file = config.state_dir.join("state.json");
-
-        if !state_file.exists() {
-            trace!("creating a new state file at {:?}", state_file);
-            return Ok(Self {
-                state_file,
-                channels: HashMap::new(),
-                last_trigger: None,
-                channel_positions: HashMap::new(),
-            });
+/// How often a single message was reposted by random draws
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageStats {
+    pub count: u64,
+    pub last_drawn_at: DateTime<Utc>,
+}
+
+/// How a user allows the messages in their monologue channel to be drawn
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Privacy {
+    /// Exclude the channel from random draws
+    #[serde(default)]
+    pub exclude_draws: bool,
+    /// Exclude the channel from `/random` and `/onthisday` when they are used
+    /// by anyone else
+    #[serde(default)]
+    pub exclude_random: bool,
+}
+
+/// A message that was reposted by a random draw
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DrawRecord {
+    /// The name of the random draw that reposted the message
+    #[serde(default = "default_draw_name")]
+    pub draw: String,
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+    pub author_id: UserId,
+    pub drawn_at: DateTime<Utc>,
+    /// Which pool of messages the message was drawn from
+    #[serde(default)]
+    pub source: DrawSource,
+}
+
+fn default_draw_name() -> String {
+    DEFAULT_DRAW.to_string()
+}
+
+/// The pool of messages a random draw picked its message from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrawSource {
+    /// Messages posted since the last draw or within the draw window, or
+    /// every message for the first draw
+    #[default]
+    New,
+    /// Messages posted on today's date in previous years
+    OnThisDay,
+    /// Every message, because nothing new was posted
+    AllTime,
+    /// Messages from the last week, because nothing new was posted
+    LastWeek,
+    /// Messages from the last month, because nothing was posted in the last
+    /// week either
+    LastMonth,
+    /// Chosen by an admin instead of drawn
+    Manual,
+}
+
+impl fmt::Display for DrawSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DrawSource::New => "new",
+            DrawSource::OnThisDay => "on_this_day",
+            DrawSource::AllTime => "all_time",
+            DrawSource::LastWeek => "last_week",
+            DrawSource::LastMonth => "last_month",
+            DrawSource::Manual => "manual",
+        })
+    }
+}
+
+impl FromStr for DrawSource {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "new" => Ok(DrawSource::New),
+            "on_this_day" => Ok(DrawSource::OnThisDay),
+            "all_time" => Ok(DrawSource::AllTime),
+            "last_week" => Ok(DrawSource::LastWeek),
+            "last_month" => Ok(DrawSource::LastMonth),
+            "manual" => Ok(DrawSource::Manual),
+            _ => Err(color_eyre::eyre::eyre!("unknown draw source `{}`", s)),
         }
+    }
+}
 
-        let state = tokio::fs::read_to_string(&state_file)
-            .await
-            .wrap_err("failed to read state file")?;
+/// The result of a random draw
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrawOutcome {
+    /// A message from the given pool was reposted
+    Drawn(DrawSource),
+    /// Nothing was found, so the fallback message was posted instead
+    FallbackMessage,
+    /// Nothing was found and nothing was posted
+    Skipped,
+}
+
+impl DrawOutcome {
+    /// A short description to show to whoever triggered the draw
+    pub fn describe(self) -> &'static str {
+        match self {
+            DrawOutcome::Drawn(DrawSource::New) => "Random draw triggered",
+            DrawOutcome::Drawn(DrawSource::OnThisDay) => {
+                "Random draw triggered with a message posted on this day"
+            }
+            DrawOutcome::Drawn(DrawSource::AllTime) => {
+                "Nothing new was posted, so the random draw picked a message from any time"
+            }
+            DrawOutcome::Drawn(DrawSource::LastWeek) => {
+                "Nothing new was posted, so the random draw picked a message from the last week"
+            }
+            DrawOutcome::Drawn(DrawSource::LastMonth) => {
+                "Nothing new was posted, so the random draw picked a message from the last month"
+            }
+            DrawOutcome::Drawn(DrawSource::Manual) => "Message reposted",
+            DrawOutcome::FallbackMessage => {
+                "Nothing new was posted, so the fallback message was sent instead"
+            }
+            DrawOutcome::Skipped => "No messages found for the random draw, nothing was sent",
+        }
+    }
+}
+
+impl fmt::Display for DrawOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DrawOutcome::Drawn(source) => source.fmt(f),
+            DrawOutcome::FallbackMessage => f.write_str("fallback_message"),
+            DrawOutcome::Skipped => f.write_str("skipped"),
+        }
+    }
+}
+
+impl FromStr for DrawOutcome {
+    type Err = color_eyre::eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fallback_message" => Ok(DrawOutcome::FallbackMessage),
+            "skipped" => Ok(DrawOutcome::Skipped),
+            _ => s.parse().map(DrawOutcome::Drawn),
+        }
+    }
+}
+
+/// A single run of a random draw, whether or not it posted anything
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DrawLogEntry {
+    /// The name of the random draw that ran
+    pub draw: String,
+    pub ran_at: DateTime<Utc>,
+    pub outcome: DrawOutcome,
+}
+
+/// The number of runs kept in the draw log for each random draw
+pub const DRAW_LOG_LENGTH: usize = 100;
+
+/// How long a reposted message is excluded from later random draws
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cooldown {
+    /// The number of draws during which the message is excluded
+    pub draws: usize,
+    /// The amount of time during which the message is excluded
+    pub duration: Duration,
+}
+
+impl Cooldown {
+    fn is_empty(&self) -> bool {
+        self.draws == 0 && self.duration <= Duration::zero()
+    }
+}
 
-        let mut state =
-            serde_json::from_str::<Self>(&state).wrap_err("failed to parse state file")?;
+impl State {
+    pub async fn load(storage: Arc<dyn Storage>) -> Result<Self> {
+        let mut guilds = storage
+            .load_state()
+            .await
+            .wrap_err("failed to load state")?
+            .unwrap_or_else(|| {
+                trace!("creating a new state");
+                HashMap::new()
+            });
 
-        state.state_file = state_file;
+        for guild in guilds.values_mut() {
+            if let Some(last_trigger) = guild.last_trigger.take() {
+                guild
+                    .last_triggers
+                    .entry(DEFAULT_DRAW.to_string())
+                    .or_insert(last_trigger);
+            }
+        }
 
-        Ok(state)
+        Ok(Self {
+            storage,
+            guilds,
+            configs: HashMap::new(),
+        })
     }
 
     async fn save(&self) -> Result<()> {
         trace!("saving state");
 
-        let state_json = serde_json::to_string(self).wrap_err("failed to serialize state")?;
-
-        tokio::fs::write(&self.state_file, state_json)
+        self.storage
+            .save_state(&self.guilds)
             .await
-            .wrap_err("failed to write serialized state")?;
+            .wrap_err("failed to save state")
+    }
 
-        Ok(())
+    fn guild_mut(&mut self, guild_id: GuildId) -> &mut GuildState {
+        self.guilds.entry(guild_id).or_default()
     }
 
-    pub fn get_channels(&self) -> Vec<ChannelId> {
-        self.channels.values().copied().collect()
+    /// Get the IDs of all guilds that have state associated with them
+    pub fn get_guilds(&self) -> Vec<GuildId> {
+        self.guilds.keys().copied().collect()
     }
 
-    pub fn get_channel(&self, user_id: UserId) -> Option<ChannelId> {
-        self.channels.get(&user_id).copied()
+    pub fn get_channels(&self, guild_id: GuildId) -> Vec<ChannelId> {
+        self.guilds
+            .get(&guild_id)
+            .map(|guild| guild.channels.values().copied().collect())
+            .unwrap_or_default()
     }
 
-    /// Check if a channel ID is a monologue channel
-    pub fn should_track(&self, channel_id: ChannelId) -> bool {
-        self.channels.values().any(|&id| id == channel_id)
+    /// Get every monologue channel in every guild
+    pub fn get_all_channels(&self) -> Vec<(GuildId, ChannelId)> {
+        self.guilds
+            .iter()
+            .flat_map(|(&guild_id, guild)| {
+                guild
+                    .channels
+                    .values()
+                    .map(move |&channel_id| (guild_id, channel_id))
+            })
+            .collect()
+    }
+
+    pub fn get_channel(&self, guild_id: GuildId, user_id: UserId) -> Option<ChannelId> {
+        self.guilds.get(&guild_id)?.channels.get(&user_id).copied()
+    }
+
+    /// Get the user who owns a monologue channel
+    pub fn channel_owner(&self, guild_id: GuildId, channel_id: ChannelId) -> Option<UserId> {
+        self.guilds
+            .get(&guild_id)?
+            .channels
+            .iter()
+            .find_map(|(&user_id, &id)| (id == channel_id).then_some(user_id))
+    }
+
+    /// Check if a channel ID is a monologue channel in the given guild
+    pub fn should_track(&self, guild_id: GuildId, channel_id: ChannelId) -> bool {
+        self.guilds
+            .get(&guild_id)
+            .is_some_and(|guild| guild.channels.values().any(|&id| id == channel_id))
     }
 
     /// Set the channel for a user ID
     ///
     /// Does not set the channel position. Caller must ensure a position is set
     /// after creation if necessary.
-    pub async fn set_channel(&mut self, user_id: UserId, channel_id: ChannelId) -> Result<()> {
-        self.channels.insert(user_id, channel_id);
+    pub async fn set_channel(
+        &mut self,
+        guild_id: GuildId,
+        user_id: UserId,
+        channel_id: ChannelId,
+    ) -> Result<()> {
+        self.guild_mut(guild_id)
+            .channels
+            .insert(user_id, channel_id);
 
         self.save().await?;
 
@@ -89,9 +363,18 @@ impl State {
 
     /// Remove the channel for a user ID
     ///
-    /// Automatically removes its channel position as well
-    pub async fn remove_channel(&mut self, channel_id: ChannelId) -> Result<Option<UserId>> {
-        let user_id = self.channels.iter().find_map(|(user_id, id)| {
+    /// Automatically removes its channel position, excluded and flagged
+    /// messages and collaborators as well
+    pub async fn remove_channel(
+        &mut self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+    ) -> Result<Option<UserId>> {
+        let Some(guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(None);
+        };
+
+        let user_id = guild.channels.iter().find_map(|(user_id, id)| {
             if *id == channel_id {
                 Some(*user_id)
             } else {
@@ -100,9 +383,12 @@ impl State {
         });
 
         if let Some(user_id) = user_id {
-            self.channels.remove(&user_id);
+            guild.channels.remove(&user_id);
 
-            self.channel_positions.remove(&channel_id);
+            guild.channel_positions.remove(&channel_id);
+            guild.excluded_messages.remove(&channel_id);
+            guild.flagged_messages.remove(&channel_id);
+            guild.collaborators.remove(&channel_id);
 
             self.save().await?;
 
@@ -114,12 +400,24 @@ impl State {
 
     /// Removes the channel for a user ID
     ///
-    /// Automatically removes its channel position as well
-    pub async fn remove_channel_for(&mut self, user_id: &UserId) -> Result<Option<ChannelId>> {
-        let id = self.channels.remove(user_id);
+    /// Automatically removes its channel position, excluded and flagged
+    /// messages and collaborators as well
+    pub async fn remove_channel_for(
+        &mut self,
+        guild_id: GuildId,
+        user_id: &UserId,
+    ) -> Result<Option<ChannelId>> {
+        let Some(guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(None);
+        };
+
+        let id = guild.channels.remove(user_id);
 
         if let Some(id) = id {
-            self.channel_positions.remove(&id);
+            guild.channel_positions.remove(&id);
+            guild.excluded_messages.remove(&id);
+            guild.flagged_messages.remove(&id);
+            guild.collaborators.remove(&id);
 
             self.save().await?;
         }
@@ -127,46 +425,500 @@ impl State {
         Ok(id)
     }
 
-    pub fn last_trigger(&self) -> Option<DateTime<Utc>> {
-        self.last_trigger
+    pub fn last_trigger(&self, guild_id: GuildId, draw: &str) -> Option<DateTime<Utc>> {
+        self.guilds.get(&guild_id)?.last_triggers.get(draw).copied()
     }
 
-    pub async fn just_triggered(&mut self) -> Result<()> {
-        self.last_trigger = Some(Utc::now());
+    pub async fn just_triggered(&mut self, guild_id: GuildId, draw: &str) -> Result<()> {
+        self.guild_mut(guild_id)
+            .last_triggers
+            .insert(draw.to_string(), Utc::now());
+
+        self.save().await?;
+
+        Ok(())
+    }
+
+    /// Get the draws of the named random draw that are still within the
+    /// cooldown, i.e. either among its last `cooldown.draws` draws or made
+    /// within `cooldown.duration`.
+    pub fn recent_draws(
+        &self,
+        guild_id: GuildId,
+        draw: &str,
+        cooldown: Cooldown,
+    ) -> Vec<DrawRecord> {
+        let Some(guild) = self.guilds.get(&guild_id) else {
+            return Vec::new();
+        };
+
+        let draws = guild
+            .draws
+            .iter()
+            .filter(|record| record.draw == draw)
+            .collect::<Vec<_>>();
+
+        let since = Utc::now() - cooldown.duration;
+        let skip = draws.len().saturating_sub(cooldown.draws);
+
+        draws
+            .into_iter()
+            .enumerate()
+            .filter(|(i, record)| *i >= skip || record.drawn_at > since)
+            .map(|(_, record)| record.clone())
+            .collect()
+    }
+
+    /// The number of messages the named random draw has reposted
+    pub fn draw_count(&self, guild_id: GuildId, draw: &str) -> u64 {
+        self.guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.draw_counts.get(draw))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Log the outcome of a run of a random draw, forgetting its oldest runs
+    /// beyond [`DRAW_LOG_LENGTH`]
+    pub async fn log_draw(
+        &mut self,
+        guild_id: GuildId,
+        draw: &str,
+        outcome: DrawOutcome,
+    ) -> Result<()> {
+        let guild = self.guild_mut(guild_id);
+
+        guild.draw_log.push_back(DrawLogEntry {
+            draw: draw.to_string(),
+            ran_at: Utc::now(),
+            outcome,
+        });
+
+        let mut excess = guild
+            .draw_log
+            .iter()
+            .filter(|entry| entry.draw == draw)
+            .count()
+            .saturating_sub(DRAW_LOG_LENGTH);
+
+        guild.draw_log.retain(|entry| {
+            if excess > 0 && entry.draw == draw {
+                excess -= 1;
+                false
+            } else {
+                true
+            }
+        });
+
+        self.save().await?;
+
+        Ok(())
+    }
+
+    /// Remember that a message was reposted by a random draw.
+    ///
+    /// Draws of the same random draw that fall outside the cooldown are
+    /// forgotten, so lengthening the cooldown only takes full effect once
+    /// enough new draws were made. Every draw is counted regardless, both for
+    /// the random draw and for the message, and its source is kept in the
+    /// draw log by [`State::log_draw`].
+    pub async fn record_draw(
+        &mut self,
+        guild_id: GuildId,
+        draw: DrawRecord,
+        cooldown: Cooldown,
+    ) -> Result<()> {
+        let name = draw.draw.clone();
+
+        let guild = self.guild_mut(guild_id);
+        *guild.draw_counts.entry(name.clone()).or_default() += 1;
+
+        guild
+            .message_stats
+            .entry(draw.message_id)
+            .and_modify(|stats| {
+                stats.count += 1;
+                stats.last_drawn_at = draw.drawn_at;
+            })
+            .or_insert(MessageStats {
+                count: 1,
+                last_drawn_at: draw.drawn_at,
+            });
+
+        let has_history = self
+            .guilds
+            .get(&guild_id)
+            .is_some_and(|guild| guild.draws.iter().any(|record| record.draw == name));
+
+        if cooldown.is_empty() && !has_history {
+            self.save().await?;
+
+            return Ok(());
+        }
+
+        self.guild_mut(guild_id).draws.push_back(draw);
+
+        let recent = self.recent_draws(guild_id, &name, cooldown);
+
+        let guild = self.guild_mut(guild_id);
+        guild.draws.retain(|record| record.draw != name);
+        guild.draws.extend(recent);
 
         self.save().await?;
 
         Ok(())
     }
 
-    pub fn channel_position(&self, channel_id: ChannelId) -> Option<u16> {
-        self.channel_positions.get(&channel_id).copied()
+    pub fn channel_position(&self, guild_id: GuildId, channel_id: ChannelId) -> Option<u16> {
+        self.guilds
+            .get(&guild_id)?
+            .channel_positions
+            .get(&channel_id)
+            .copied()
     }
 
     pub async fn set_channel_position(
         &mut self,
+        guild_id: GuildId,
         channel_id: ChannelId,
         position: u16,
     ) -> Result<()> {
-        self.channel_positions.insert(channel_id, position);
+        self.guild_mut(guild_id)
+            .channel_positions
+            .insert(channel_id, position);
 
         self.save().await?;
 
         Ok(())
     }
 
+    /// Get the config overrides for a guild
+    pub fn settings(&self, guild_id: GuildId) -> GuildSettings {
+        self.guilds
+            .get(&guild_id)
+            .map(|guild| guild.settings.clone())
+            .unwrap_or_default()
+    }
+
+    /// Get the effective config for a guild, resolving it from its settings
+    /// only if they changed since it was last resolved
+    pub fn guild_config(&mut self, config: &AppConfig, guild_id: GuildId) -> Arc<GuildConfig> {
+        if let Some(resolved) = self.configs.get(&guild_id) {
+            return resolved.clone();
+        }
+
+        let settings = self.settings(guild_id);
+        let resolved = Arc::new(GuildConfig::resolve(config, &settings));
+        self.configs.insert(guild_id, resolved.clone());
+
+        resolved
+    }
+
+    pub async fn set_settings(&mut self, guild_id: GuildId, settings: GuildSettings) -> Result<()> {
+        self.guild_mut(guild_id).settings = settings;
+        self.configs.remove(&guild_id);
+
+        self.save().await?;
+
+        Ok(())
+    }
+
+    /// How often a message was reposted by random draws, if ever
+    pub fn message_stats(&self, guild_id: GuildId, message_id: MessageId) -> Option<MessageStats> {
+        self.guilds
+            .get(&guild_id)?
+            .message_stats
+            .get(&message_id)
+            .copied()
+    }
+
+    /// Forget everything about a message that was deleted
+    pub async fn forget_message(
+        &mut self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> Result<()> {
+        let Some(guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(());
+        };
+
+        let had_stats = guild.message_stats.remove(&message_id).is_some();
+        let was_flagged = remove_from(&mut guild.flagged_messages, channel_id, message_id);
+        let was_excluded = self
+            .set_message_excluded(guild_id, channel_id, message_id, false)
+            .await?;
+
+        // `set_message_excluded` already saved if the message was excluded
+        if (had_stats || was_flagged) && !was_excluded {
+            self.save().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the privacy choices of a user
+    pub fn privacy(&self, guild_id: GuildId, user_id: UserId) -> Privacy {
+        self.guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.privacy.get(&user_id))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub async fn set_privacy(
+        &mut self,
+        guild_id: GuildId,
+        user_id: UserId,
+        privacy: Privacy,
+    ) -> Result<()> {
+        let guild = self.guild_mut(guild_id);
+
+        if privacy == Privacy::default() {
+            guild.privacy.remove(&user_id);
+        } else {
+            guild.privacy.insert(user_id, privacy);
+        }
+
+        self.save().await?;
+
+        Ok(())
+    }
+
+    /// Check if a message was excluded from draws by its author
+    pub fn is_message_excluded(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> bool {
+        self.guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.excluded_messages.get(&channel_id))
+            .is_some_and(|messages| messages.contains(&message_id))
+    }
+
+    /// Exclude a message from draws or include it again. Returns whether
+    /// anything changed
+    pub async fn set_message_excluded(
+        &mut self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        excluded: bool,
+    ) -> Result<bool> {
+        let guild = self.guild_mut(guild_id);
+
+        let changed = if excluded {
+            guild
+                .excluded_messages
+                .entry(channel_id)
+                .or_default()
+                .insert(message_id)
+        } else {
+            remove_from(&mut guild.excluded_messages, channel_id, message_id)
+        };
+
+        if changed {
+            self.save().await?;
+        }
+
+        Ok(changed)
+    }
+
+    /// Check if a message was flagged as a foreign post
+    pub fn is_message_flagged(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> bool {
+        self.guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.flagged_messages.get(&channel_id))
+            .is_some_and(|messages| messages.contains(&message_id))
+    }
+
+    /// Remember that a message was flagged as a foreign post, which keeps it
+    /// from ever being drawn
+    pub async fn flag_message(
+        &mut self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> Result<()> {
+        let flagged = self
+            .guild_mut(guild_id)
+            .flagged_messages
+            .entry(channel_id)
+            .or_default()
+            .insert(message_id);
+
+        if flagged {
+            self.save().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get who may post in each monologue channel of the guild
+    pub fn ownership(&self, guild_id: GuildId, config: &MonologuesConfig) -> Ownership {
+        let Some(guild) = self.guilds.get(&guild_id) else {
+            return Ownership::new(config, HashMap::new(), HashMap::new());
+        };
+
+        let owners = guild
+            .channels
+            .iter()
+            .map(|(&user_id, &channel_id)| (channel_id, user_id))
+            .collect();
+
+        Ownership::new(config, owners, guild.collaborators.clone())
+    }
+
+    /// Get the collaborators of a monologue channel
+    pub fn collaborators(&self, guild_id: GuildId, channel_id: ChannelId) -> HashSet<UserId> {
+        self.guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.collaborators.get(&channel_id))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Check if a user is a collaborator of a monologue channel
+    pub fn is_collaborator(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        user_id: UserId,
+    ) -> bool {
+        self.guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.collaborators.get(&channel_id))
+            .is_some_and(|collaborators| collaborators.contains(&user_id))
+    }
+
+    /// Add a collaborator to a monologue channel or remove them. Returns
+    /// whether anything changed
+    pub async fn set_collaborator(
+        &mut self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        user_id: UserId,
+        collaborating: bool,
+    ) -> Result<bool> {
+        let guild = self.guild_mut(guild_id);
+
+        let changed = if collaborating {
+            guild
+                .collaborators
+                .entry(channel_id)
+                .or_default()
+                .insert(user_id)
+        } else {
+            remove_from(&mut guild.collaborators, channel_id, user_id)
+        };
+
+        if changed {
+            self.save().await?;
+        }
+
+        Ok(changed)
+    }
+
+    /// Get which user's pool each message in the guild counts toward
+    pub fn pools(&self, guild_id: GuildId, collaborator_posts: CollaboratorPosts) -> Pools {
+        let Some(guild) = self.guilds.get(&guild_id) else {
+            return Pools::default();
+        };
+
+        let owners = guild
+            .channels
+            .iter()
+            .map(|(&user_id, &channel_id)| (channel_id, user_id))
+            .collect();
+
+        let collaborators = match collaborator_posts {
+            CollaboratorPosts::Owner => HashMap::new(),
+            CollaboratorPosts::Author => guild.collaborators.clone(),
+        };
+
+        Pools {
+            owners,
+            collaborators,
+        }
+    }
+
+    /// Get the pools and messages that must be skipped when drawing a
+    /// message. `requester` is the user drawing a message with a command like
+    /// `/random`, or `None` for random draws.
+    ///
+    /// Users can't opt their pool out of their own commands.
+    pub fn exclusions(
+        &self,
+        guild_id: GuildId,
+        requester: Option<UserId>,
+        collaborator_posts: CollaboratorPosts,
+    ) -> Exclusions {
+        let Some(guild) = self.guilds.get(&guild_id) else {
+            return Exclusions::default();
+        };
+
+        let users = guild
+            .privacy
+            .iter()
+            .filter(|(&user_id, privacy)| match requester {
+                Some(requester) => privacy.exclude_random && user_id != requester,
+                None => privacy.exclude_draws,
+            })
+            .map(|(&user_id, _)| user_id)
+            .collect();
+
+        let messages = guild
+            .excluded_messages
+            .values()
+            .chain(guild.flagged_messages.values())
+            .flatten()
+            .copied()
+            .collect();
+
+        Exclusions {
+            users,
+            messages,
+            pools: self.pools(guild_id, collaborator_posts),
+        }
+    }
+
     /// Get the next position to use for a channel. The order of channels is
     /// descending, so the next position will be the lowest number in the map
     /// minus 1. If this returns zero, it is time to move all the channels back
     /// to u16::MAX and start over.
-    pub fn next_position(&self) -> u16 {
+    pub fn next_position(&self, guild_id: GuildId) -> u16 {
         // we use unwrap or default so if there are no entries, we return zero
         // which will trigger the reset
-        self.channel_positions
-            .values()
-            .copied()
-            .min()
+        self.guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.channel_positions.values().copied().min())
             .map(|pos| pos - 1)
             .unwrap_or_default()
     }
 }
+
+/// Remove `value` from the set of `channel_id`, dropping the set once it is
+/// empty. Returns whether the value was in the set
+fn remove_from<T: Eq + std::hash::Hash>(
+    sets: &mut HashMap<ChannelId, HashSet<T>>,
+    channel_id: ChannelId,
+    value: T,
+) -> bool {
+    let Some(set) = sets.get_mut(&channel_id) else {
+        return false;
+    };
+
+    let removed = set.remove(&value);
+    if set.is_empty() {
+        sets.remove(&channel_id);
+    }
+
+    removed
+}
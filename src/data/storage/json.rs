@@ -0,0 +1,146 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use color_eyre::eyre::{Result, WrapErr};
+use poise::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serenity::all::GuildId;
+use tokio::sync::Mutex;
+
+use crate::data::{
+    config::AppConfig,
+    index::{GuildIndex, IndexEvent},
+    journal::Journal,
+    persist,
+    state::GuildState,
+    storage::Storage,
+};
+
+/// The number of journaled events after which the index is compacted into a new
+/// snapshot
+const COMPACT_AFTER: usize = 1000;
+
+/// The layout of both `state.json` and `index.json`
+#[derive(Serialize, Deserialize)]
+struct Snapshot<T> {
+    guilds: T,
+}
+
+/// Stores the state and index as JSON files in the state directory.
+///
+/// The state is rewritten in full on every change. Changes to the index are
+/// appended to a journal which is periodically compacted into a snapshot.
+#[derive(Debug)]
+pub struct JsonStorage {
+    state_file: PathBuf,
+    index_file: PathBuf,
+    /// Changes made to the index since the snapshot in `index_file` was taken
+    journal: Mutex<Journal<IndexEvent>>,
+}
+
+impl JsonStorage {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            state_file: config.state_dir.join("state.json"),
+            index_file: config.state_dir.join("index.json"),
+            journal: Mutex::new(Journal::new(config.state_dir.join("index.journal"))),
+        }
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load_state(&self) -> BoxFuture<'_, Result<Option<HashMap<GuildId, GuildState>>>> {
+        Box::pin(async move {
+            let snapshot = persist::load_json::<Snapshot<_>>(&self.state_file)
+                .await
+                .wrap_err("failed to load state file")?;
+
+            Ok(snapshot.map(|s| s.guilds))
+        })
+    }
+
+    fn save_state<'a>(
+        &'a self,
+        guilds: &'a HashMap<GuildId, GuildState>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            persist::save_json(&self.state_file, &Snapshot { guilds })
+                .await
+                .wrap_err("failed to write state file")
+        })
+    }
+
+    fn load_index(&self) -> BoxFuture<'_, Result<Option<HashMap<GuildId, GuildIndex>>>> {
+        Box::pin(async move {
+            let snapshot = persist::load_json::<Snapshot<_>>(&self.index_file)
+                .await
+                .wrap_err("failed to load index file")?;
+
+            let mut journal = self.journal.lock().await;
+
+            let (opened, events) = Journal::open(self.index_file.with_extension("journal"))
+                .await
+                .wrap_err("failed to open index journal")?;
+            *journal = opened;
+
+            if snapshot.is_none() && events.is_empty() {
+                return Ok(None);
+            }
+
+            let mut guilds = snapshot.map(|s| s.guilds).unwrap_or_default();
+
+            trace!("replaying {} journaled index events", events.len());
+            for event in events {
+                event.apply(&mut guilds);
+            }
+
+            Ok(Some(guilds))
+        })
+    }
+
+    fn save_index<'a>(
+        &'a self,
+        guilds: &'a HashMap<GuildId, GuildIndex>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            persist::save_json(&self.index_file, &Snapshot { guilds })
+                .await
+                .wrap_err("failed to write index file")?;
+
+            // the snapshot now contains every journaled event. if we crash
+            // before clearing, the events are just replayed again on startup,
+            // which is harmless since applying them is idempotent
+            self.journal
+                .lock()
+                .await
+                .clear()
+                .await
+                .wrap_err("failed to clear index journal")
+        })
+    }
+
+    fn record_index_event<'a>(
+        &'a self,
+        event: &'a IndexEvent,
+        guilds: &'a HashMap<GuildId, GuildIndex>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let event_count = {
+                let mut journal = self.journal.lock().await;
+
+                journal
+                    .append(event)
+                    .await
+                    .wrap_err("failed to journal index event")?;
+
+                journal.event_count()
+            };
+
+            if event_count >= COMPACT_AFTER {
+                debug!("compacting index journal");
+                self.save_index(guilds).await?;
+            }
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,75 @@
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+
+use color_eyre::eyre::{Result, WrapErr};
+use poise::BoxFuture;
+use serenity::all::GuildId;
+
+use crate::data::{
+    config::{AppConfig, StorageBackend},
+    index::{GuildIndex, IndexEvent},
+    state::GuildState,
+};
+
+pub mod json;
+pub mod sqlite;
+
+/// A place to persist the [`State`](super::state::State) and
+/// [`Index`](super::index::Index).
+///
+/// Both are always held in memory in their entirety, so a backend only needs
+/// to be able to load everything at once and persist changes.
+pub trait Storage: Debug + Send + Sync {
+    /// Load the state of every guild. Returns `None` if no state has been
+    /// saved yet.
+    fn load_state(&self) -> BoxFuture<'_, Result<Option<HashMap<GuildId, GuildState>>>>;
+
+    /// Replace the stored state with the provided state.
+    fn save_state<'a>(
+        &'a self,
+        guilds: &'a HashMap<GuildId, GuildState>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Load the index of every guild, including every change recorded with
+    /// [`Storage::record_index_event`]. Returns `None` if no index has been
+    /// saved yet.
+    fn load_index(&self) -> BoxFuture<'_, Result<Option<HashMap<GuildId, GuildIndex>>>>;
+
+    /// Replace the stored index with the provided index.
+    fn save_index<'a>(
+        &'a self,
+        guilds: &'a HashMap<GuildId, GuildIndex>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Persist a single change to the index. `guilds` is the full index with
+    /// the event already applied, which backends may use to take a new
+    /// snapshot.
+    fn record_index_event<'a>(
+        &'a self,
+        event: &'a IndexEvent,
+        guilds: &'a HashMap<GuildId, GuildIndex>,
+    ) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Open the storage backend selected in the config.
+pub async fn open(config: &AppConfig) -> Result<Arc<dyn Storage>> {
+    match config.storage {
+        StorageBackend::Json => {
+            debug!("using JSON storage");
+
+            Ok(Arc::new(json::JsonStorage::new(config)))
+        }
+        StorageBackend::Sqlite => {
+            debug!("using SQLite storage");
+
+            let storage =
+                sqlite::SqliteStorage::open(config).wrap_err("failed to open SQLite database")?;
+
+            storage
+                .import_json(config)
+                .await
+                .wrap_err("failed to import JSON state into SQLite")?;
+
+            Ok(Arc::new(storage))
+        }
+    }
+}
@@ -0,0 +1,1090 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use chrono::DateTime;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use poise::BoxFuture;
+use rusqlite::{
+    params, params_from_iter, types::ToSqlOutput, Connection, OptionalExtension, ToSql, Transaction,
+};
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+
+use crate::data::{
+    config::{AppConfig, DEFAULT_DRAW},
+    index::{GuildIndex, IndexEvent, IndexedMessage, MessageMeta},
+    state::{DrawLogEntry, DrawRecord, GuildState, MessageStats, Privacy},
+    storage::{json::JsonStorage, Storage},
+};
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS guilds (
+    guild_id INTEGER PRIMARY KEY,
+    settings TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS channels (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL UNIQUE,
+    PRIMARY KEY (guild_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS positions (
+    guild_id INTEGER NOT NULL,
+    channel_id INTEGER PRIMARY KEY,
+    position INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS draw_history (
+    guild_id INTEGER NOT NULL,
+    triggered_at INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, triggered_at)
+);
+
+CREATE TABLE IF NOT EXISTS draw_triggers (
+    guild_id INTEGER NOT NULL,
+    draw TEXT NOT NULL,
+    triggered_at INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, draw)
+);
+
+CREATE TABLE IF NOT EXISTS draws (
+    guild_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    author_id INTEGER NOT NULL,
+    drawn_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS draw_counts (
+    guild_id INTEGER NOT NULL,
+    draw TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, draw)
+);
+
+CREATE TABLE IF NOT EXISTS privacy (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    exclude_draws INTEGER NOT NULL,
+    exclude_random INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS excluded_messages (
+    guild_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    message_id INTEGER PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS flagged_messages (
+    guild_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    message_id INTEGER PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS collaborators (
+    guild_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    PRIMARY KEY (channel_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS draw_log (
+    guild_id INTEGER NOT NULL,
+    draw TEXT NOT NULL,
+    ran_at INTEGER NOT NULL,
+    outcome TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS message_stats (
+    guild_id INTEGER NOT NULL,
+    message_id INTEGER PRIMARY KEY,
+    count INTEGER NOT NULL,
+    last_drawn_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS messages (
+    guild_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    PRIMARY KEY (channel_id, message_id)
+);
+
+CREATE INDEX IF NOT EXISTS messages_by_guild ON messages (guild_id, message_id);
+
+CREATE TABLE IF NOT EXISTS last_indexed (
+    guild_id INTEGER NOT NULL,
+    channel_id INTEGER PRIMARY KEY,
+    message_id INTEGER NOT NULL
+);
+"#;
+
+/// Columns added to existing tables after they were first created. Each is
+/// added to databases that don't have it yet when the database is opened.
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("messages", "author_id", "INTEGER"),
+    // the JSON serialized `MessageMeta`
+    ("messages", "meta", "TEXT"),
+    // the `DrawSource` of the draw, NULL for draws made before it was recorded
+    ("draws", "source", "TEXT"),
+    // the name of the random draw, NULL for draws made before there were
+    // multiple
+    ("draws", "draw", "TEXT"),
+];
+
+/// Older databases kept every trigger of each draw, with the trigger time as
+/// part of the primary key. Only the latest trigger is used, so the table is
+/// rebuilt with one row per draw.
+fn migrate_draw_triggers(conn: &Connection) -> Result<()> {
+    let keeps_history = conn
+        .prepare(
+            "SELECT 1 FROM pragma_table_info('draw_triggers') \
+             WHERE name = 'triggered_at' AND pk > 0",
+        )?
+        .exists([])?;
+
+    if !keeps_history {
+        return Ok(());
+    }
+
+    debug!("keeping only the latest trigger of each draw");
+
+    conn.execute_batch(
+        "BEGIN;
+        CREATE TABLE draw_triggers_latest (
+            guild_id INTEGER NOT NULL,
+            draw TEXT NOT NULL,
+            triggered_at INTEGER NOT NULL,
+            PRIMARY KEY (guild_id, draw)
+        );
+        INSERT INTO draw_triggers_latest (guild_id, draw, triggered_at)
+            SELECT guild_id, draw, MAX(triggered_at) FROM draw_triggers GROUP BY guild_id, draw;
+        DROP TABLE draw_triggers;
+        ALTER TABLE draw_triggers_latest RENAME TO draw_triggers;
+        COMMIT;",
+    )?;
+
+    Ok(())
+}
+
+/// Discord IDs are `u64`s but SQLite only has signed integers. Snowflakes will
+/// not exceed `i64::MAX` for a very long time, so a plain cast is fine.
+fn to_sql(id: impl Into<u64>) -> i64 {
+    id.into() as i64
+}
+
+fn from_sql(id: i64) -> u64 {
+    id as u64
+}
+
+/// Stores the state and index in an embedded SQLite database at
+/// `mawnolaug.db` in the state directory.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+    /// The rows of each of [`STATE_TABLES`] as they were last saved, so later
+    /// saves only write what changed. `None` until the state is first saved,
+    /// which rewrites every table
+    saved_state: Mutex<Option<Vec<SavedRows>>>,
+}
+
+impl SqliteStorage {
+    pub fn open(config: &AppConfig) -> Result<Self> {
+        let conn = Connection::open(config.state_dir.join("mawnolaug.db"))
+            .wrap_err("failed to open database")?;
+
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .wrap_err("failed to enable WAL")?;
+
+        conn.execute_batch(SCHEMA)
+            .wrap_err("failed to create schema")?;
+
+        for (table, column, kind) in ADDED_COLUMNS {
+            let exists = conn
+                .prepare(&format!(
+                    "SELECT 1 FROM pragma_table_info('{table}') WHERE name = ?1"
+                ))?
+                .exists([column])?;
+
+            if !exists {
+                debug!("adding column {column} to table {table}");
+                conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {kind}"))
+                    .wrap_err_with(|| format!("failed to add column {column} to {table}"))?;
+            }
+        }
+
+        migrate_draw_triggers(&conn).wrap_err("failed to migrate draw triggers")?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            saved_state: Mutex::new(None),
+        })
+    }
+
+    /// Run `f` with exclusive access to the connection.
+    ///
+    /// SQLite calls are blocking, so this moves the current task off of the
+    /// async worker thread for the duration.
+    fn with_conn<T>(&self, f: impl FnOnce(&mut Connection) -> Result<T>) -> Result<T> {
+        tokio::task::block_in_place(|| {
+            let mut conn = self
+                .conn
+                .lock()
+                .map_err(|_| eyre!("database connection lock was poisoned"))?;
+
+            f(&mut conn)
+        })
+    }
+
+    /// Run `f` inside of a transaction, committing if it succeeds.
+    fn transaction<T>(&self, f: impl FnOnce(&Transaction<'_>) -> Result<T>) -> Result<T> {
+        self.with_conn(|conn| {
+            let tx = conn.transaction()?;
+            let value = f(&tx)?;
+            tx.commit()?;
+
+            Ok(value)
+        })
+    }
+
+    /// Import the JSON state and index files into the database.
+    ///
+    /// This only happens if the database is empty, so switching an existing
+    /// deployment over to SQLite keeps its data. The JSON files are left
+    /// untouched.
+    pub async fn import_json(&self, config: &AppConfig) -> Result<()> {
+        let is_empty = self.with_conn(|conn| {
+            let guild: Option<i64> = conn
+                .query_row("SELECT guild_id FROM guilds LIMIT 1", [], |row| row.get(0))
+                .optional()?;
+            let message: Option<i64> = conn
+                .query_row("SELECT message_id FROM messages LIMIT 1", [], |row| {
+                    row.get(0)
+                })
+                .optional()?;
+
+            Ok(guild.is_none() && message.is_none())
+        })?;
+
+        if !is_empty {
+            return Ok(());
+        }
+
+        let json = JsonStorage::new(config);
+
+        if let Some(state) = json.load_state().await? {
+            info!("importing {} guilds from state.json", state.len());
+            self.save_state(&state).await?;
+        }
+
+        if let Some(index) = json.load_index().await? {
+            info!("importing {} guild indexes from index.json", index.len());
+            self.save_index(&index).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A single value in a row of a table, comparable so that rows can be diffed
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Cell {
+    Integer(i64),
+    Text(String),
+}
+
+impl ToSql for Cell {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match self {
+            Cell::Integer(value) => value.to_sql(),
+            Cell::Text(value) => value.to_sql(),
+        }
+    }
+}
+
+impl From<i64> for Cell {
+    fn from(value: i64) -> Self {
+        Cell::Integer(value)
+    }
+}
+
+impl From<u64> for Cell {
+    fn from(value: u64) -> Self {
+        Cell::Integer(value as i64)
+    }
+}
+
+impl From<u16> for Cell {
+    fn from(value: u16) -> Self {
+        Cell::Integer(value.into())
+    }
+}
+
+impl From<bool> for Cell {
+    fn from(value: bool) -> Self {
+        Cell::Integer(value.into())
+    }
+}
+
+impl From<String> for Cell {
+    fn from(value: String) -> Self {
+        Cell::Text(value)
+    }
+}
+
+/// Build a row from anything that converts into its cells
+macro_rules! row {
+    ($($value:expr),* $(,)?) => {
+        vec![$(Cell::from($value)),*]
+    };
+}
+
+/// A row of a table, split into the columns that identify it and the rest
+type Row = (Vec<Cell>, Vec<Cell>);
+
+/// The rows of a table as they were last saved, by the columns that identify
+/// them
+type SavedRows = HashMap<Vec<Cell>, Vec<Cell>>;
+
+/// A table that part of the state is saved in
+struct Table {
+    name: &'static str,
+    /// The columns that identify a row. Tables that hold lists rather than
+    /// maps use every column
+    key: &'static [&'static str],
+    /// The remaining columns
+    values: &'static [&'static str],
+    /// The rows of a guild, in order
+    rows: fn(i64, &GuildState) -> Result<Vec<Row>>,
+}
+
+/// Every table the state is saved in
+const STATE_TABLES: [Table; 12] = [
+    Table {
+        name: "guilds",
+        key: &["guild_id"],
+        values: &["settings"],
+        rows: |guild_id, guild| {
+            let settings =
+                serde_json::to_string(&guild.settings).wrap_err("failed to serialize settings")?;
+
+            Ok(vec![(row![guild_id], row![settings])])
+        },
+    },
+    Table {
+        name: "channels",
+        key: &["guild_id", "user_id"],
+        values: &["channel_id"],
+        rows: |guild_id, guild| {
+            Ok(guild
+                .channels
+                .iter()
+                .map(|(&user_id, &channel_id)| {
+                    (row![guild_id, to_sql(user_id)], row![to_sql(channel_id)])
+                })
+                .collect())
+        },
+    },
+    Table {
+        name: "positions",
+        key: &["channel_id"],
+        values: &["guild_id", "position"],
+        rows: |guild_id, guild| {
+            Ok(guild
+                .channel_positions
+                .iter()
+                .map(|(&channel_id, &position)| {
+                    (row![to_sql(channel_id)], row![guild_id, position])
+                })
+                .collect())
+        },
+    },
+    // only the latest trigger of each draw is kept, replacing the previous
+    Table {
+        name: "draw_triggers",
+        key: &["guild_id", "draw"],
+        values: &["triggered_at"],
+        rows: |guild_id, guild| {
+            Ok(guild
+                .last_triggers
+                .iter()
+                .map(|(draw, last_trigger)| {
+                    (
+                        row![guild_id, draw.clone()],
+                        row![last_trigger.timestamp_millis()],
+                    )
+                })
+                .collect())
+        },
+    },
+    Table {
+        name: "draws",
+        key: &[
+            "guild_id",
+            "channel_id",
+            "message_id",
+            "author_id",
+            "drawn_at",
+            "source",
+            "draw",
+        ],
+        values: &[],
+        rows: |guild_id, guild| {
+            Ok(guild
+                .draws
+                .iter()
+                .map(|draw| {
+                    let key = row![
+                        guild_id,
+                        to_sql(draw.channel_id),
+                        to_sql(draw.message_id),
+                        to_sql(draw.author_id),
+                        draw.drawn_at.timestamp_millis(),
+                        draw.source.to_string(),
+                        draw.draw.clone(),
+                    ];
+
+                    (key, Vec::new())
+                })
+                .collect())
+        },
+    },
+    Table {
+        name: "draw_counts",
+        key: &["guild_id", "draw"],
+        values: &["count"],
+        rows: |guild_id, guild| {
+            Ok(guild
+                .draw_counts
+                .iter()
+                .map(|(draw, &count)| (row![guild_id, draw.clone()], row![count]))
+                .collect())
+        },
+    },
+    Table {
+        name: "privacy",
+        key: &["guild_id", "user_id"],
+        values: &["exclude_draws", "exclude_random"],
+        rows: |guild_id, guild| {
+            Ok(guild
+                .privacy
+                .iter()
+                .map(|(&user_id, privacy)| {
+                    (
+                        row![guild_id, to_sql(user_id)],
+                        row![privacy.exclude_draws, privacy.exclude_random],
+                    )
+                })
+                .collect())
+        },
+    },
+    Table {
+        name: "excluded_messages",
+        key: &["message_id"],
+        values: &["guild_id", "channel_id"],
+        rows: |guild_id, guild| Ok(message_rows(guild_id, &guild.excluded_messages)),
+    },
+    Table {
+        name: "flagged_messages",
+        key: &["message_id"],
+        values: &["guild_id", "channel_id"],
+        rows: |guild_id, guild| Ok(message_rows(guild_id, &guild.flagged_messages)),
+    },
+    Table {
+        name: "collaborators",
+        key: &["channel_id", "user_id"],
+        values: &["guild_id"],
+        rows: |guild_id, guild| {
+            Ok(guild
+                .collaborators
+                .iter()
+                .flat_map(|(&channel_id, collaborators)| {
+                    collaborators.iter().map(move |&user_id| {
+                        (row![to_sql(channel_id), to_sql(user_id)], row![guild_id])
+                    })
+                })
+                .collect())
+        },
+    },
+    Table {
+        name: "draw_log",
+        key: &["guild_id", "draw", "ran_at", "outcome"],
+        values: &[],
+        rows: |guild_id, guild| {
+            Ok(guild
+                .draw_log
+                .iter()
+                .map(|entry| {
+                    let key = row![
+                        guild_id,
+                        entry.draw.clone(),
+                        entry.ran_at.timestamp_millis(),
+                        entry.outcome.to_string(),
+                    ];
+
+                    (key, Vec::new())
+                })
+                .collect())
+        },
+    },
+    Table {
+        name: "message_stats",
+        key: &["message_id"],
+        values: &["guild_id", "count", "last_drawn_at"],
+        rows: |guild_id, guild| {
+            Ok(guild
+                .message_stats
+                .iter()
+                .map(|(&message_id, stats)| {
+                    (
+                        row![to_sql(message_id)],
+                        row![
+                            guild_id,
+                            stats.count,
+                            stats.last_drawn_at.timestamp_millis()
+                        ],
+                    )
+                })
+                .collect())
+        },
+    },
+];
+
+/// The rows of a set of messages by channel, for `excluded_messages` and
+/// `flagged_messages`
+fn message_rows(guild_id: i64, messages: &HashMap<ChannelId, HashSet<MessageId>>) -> Vec<Row> {
+    messages
+        .iter()
+        .flat_map(|(&channel_id, messages)| {
+            messages.iter().map(move |&message_id| {
+                (row![to_sql(message_id)], row![guild_id, to_sql(channel_id)])
+            })
+        })
+        .collect()
+}
+
+impl Table {
+    /// Bring the table from its `previous` rows to `rows`, deleting the rows
+    /// that are gone and replacing the ones that are new or changed. Rows are
+    /// written in order, so lists keep theirs.
+    fn save(&self, tx: &Transaction<'_>, previous: &SavedRows, rows: &[Row]) -> Result<()> {
+        let condition = self
+            .key
+            .iter()
+            .enumerate()
+            .map(|(i, column)| format!("{} = ?{}", column, i + 1))
+            .collect::<Vec<_>>();
+        let mut delete = tx.prepare_cached(&format!(
+            "DELETE FROM {} WHERE {}",
+            self.name,
+            condition.join(" AND ")
+        ))?;
+
+        let columns = self.key.iter().chain(self.values).copied();
+        let placeholders = (1..=self.key.len() + self.values.len())
+            .map(|i| format!("?{}", i))
+            .collect::<Vec<_>>();
+        let mut insert = tx.prepare_cached(&format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            self.name,
+            columns.collect::<Vec<_>>().join(", "),
+            placeholders.join(", ")
+        ))?;
+
+        let keys = rows.iter().map(|(key, _)| key).collect::<HashSet<_>>();
+
+        for key in previous.keys().filter(|key| !keys.contains(key)) {
+            delete.execute(params_from_iter(key))?;
+        }
+
+        for (key, values) in rows {
+            match previous.get(key) {
+                Some(previous) if previous == values => continue,
+                Some(_) => {
+                    delete.execute(params_from_iter(key))?;
+                }
+                None => {}
+            }
+
+            insert.execute(params_from_iter(key.iter().chain(values)))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Split message metadata into its `author_id` and `meta` columns
+fn meta_columns(meta: Option<&MessageMeta>) -> Result<(Option<i64>, Option<String>)> {
+    let Some(meta) = meta else {
+        return Ok((None, None));
+    };
+
+    let json = serde_json::to_string(meta).wrap_err("failed to serialize message metadata")?;
+
+    Ok((Some(to_sql(meta.author_id)), Some(json)))
+}
+
+/// Point `last_indexed` for a channel at whatever the in-memory index says it
+/// should be.
+fn sync_last_indexed(
+    tx: &Transaction<'_>,
+    guilds: &HashMap<GuildId, GuildIndex>,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Result<()> {
+    let last_indexed = guilds
+        .get(&guild_id)
+        .and_then(|guild| guild.last_indexed.get(&channel_id));
+
+    match last_indexed {
+        Some(&message_id) => {
+            tx.execute(
+                "INSERT OR REPLACE INTO last_indexed (guild_id, channel_id, message_id) VALUES (?1, ?2, ?3)",
+                params![to_sql(guild_id), to_sql(channel_id), to_sql(message_id)],
+            )?;
+        }
+        None => {
+            tx.execute(
+                "DELETE FROM last_indexed WHERE channel_id = ?1",
+                params![to_sql(channel_id)],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+impl Storage for SqliteStorage {
+    fn load_state(&self) -> BoxFuture<'_, Result<Option<HashMap<GuildId, GuildState>>>> {
+        Box::pin(async move {
+            self.with_conn(|conn| {
+                let mut guilds = HashMap::<GuildId, GuildState>::new();
+
+                let mut stmt = conn.prepare("SELECT guild_id, settings FROM guilds")?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+                    let settings: String = row.get(1)?;
+
+                    guilds.entry(guild_id).or_default().settings =
+                        serde_json::from_str(&settings).wrap_err("failed to parse settings")?;
+                }
+
+                if guilds.is_empty() {
+                    return Ok(None);
+                }
+
+                let mut stmt =
+                    conn.prepare("SELECT guild_id, user_id, channel_id FROM channels")?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+
+                    guilds.entry(guild_id).or_default().channels.insert(
+                        UserId::new(from_sql(row.get(1)?)),
+                        ChannelId::new(from_sql(row.get(2)?)),
+                    );
+                }
+
+                let mut stmt =
+                    conn.prepare("SELECT guild_id, channel_id, position FROM positions")?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+
+                    guilds
+                        .entry(guild_id)
+                        .or_default()
+                        .channel_positions
+                        .insert(ChannelId::new(from_sql(row.get(1)?)), row.get(2)?);
+                }
+
+                // triggers from before each draw had its own are only used if
+                // the default draw hasn't been triggered since
+                let mut stmt = conn.prepare(
+                    "SELECT guild_id, MAX(triggered_at) FROM draw_history GROUP BY guild_id",
+                )?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+
+                    guilds.entry(guild_id).or_default().last_trigger =
+                        DateTime::from_timestamp_millis(row.get(1)?);
+                }
+
+                let mut stmt = conn.prepare(
+                    "SELECT guild_id, draw, triggered_at FROM draw_triggers",
+                )?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+                    let triggered_at = DateTime::from_timestamp_millis(row.get(2)?)
+                        .ok_or_else(|| eyre!("invalid trigger timestamp"))?;
+
+                    guilds
+                        .entry(guild_id)
+                        .or_default()
+                        .last_triggers
+                        .insert(row.get(1)?, triggered_at);
+                }
+
+                let mut stmt = conn.prepare(
+                    "SELECT guild_id, channel_id, message_id, author_id, drawn_at, source, draw FROM draws ORDER BY rowid",
+                )?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+                    let drawn_at = DateTime::from_timestamp_millis(row.get(4)?)
+                        .ok_or_else(|| eyre!("invalid draw timestamp"))?;
+                    let source = row
+                        .get::<_, Option<String>>(5)?
+                        .map(|source| source.parse())
+                        .transpose()?
+                        .unwrap_or_default();
+
+                    guilds
+                        .entry(guild_id)
+                        .or_default()
+                        .draws
+                        .push_back(DrawRecord {
+                            draw: row
+                                .get::<_, Option<String>>(6)?
+                                .unwrap_or_else(|| DEFAULT_DRAW.to_string()),
+                            channel_id: ChannelId::new(from_sql(row.get(1)?)),
+                            message_id: MessageId::new(from_sql(row.get(2)?)),
+                            author_id: UserId::new(from_sql(row.get(3)?)),
+                            drawn_at,
+                            source,
+                        });
+                }
+
+                let mut stmt = conn.prepare("SELECT guild_id, draw, count FROM draw_counts")?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+
+                    guilds
+                        .entry(guild_id)
+                        .or_default()
+                        .draw_counts
+                        .insert(row.get(1)?, row.get(2)?);
+                }
+
+                let mut stmt = conn.prepare(
+                    "SELECT guild_id, user_id, exclude_draws, exclude_random FROM privacy",
+                )?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+
+                    guilds.entry(guild_id).or_default().privacy.insert(
+                        UserId::new(from_sql(row.get(1)?)),
+                        Privacy {
+                            exclude_draws: row.get(2)?,
+                            exclude_random: row.get(3)?,
+                        },
+                    );
+                }
+
+                let mut stmt =
+                    conn.prepare("SELECT guild_id, channel_id, message_id FROM excluded_messages")?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+
+                    guilds
+                        .entry(guild_id)
+                        .or_default()
+                        .excluded_messages
+                        .entry(ChannelId::new(from_sql(row.get(1)?)))
+                        .or_default()
+                        .insert(MessageId::new(from_sql(row.get(2)?)));
+                }
+
+                let mut stmt =
+                    conn.prepare("SELECT guild_id, channel_id, message_id FROM flagged_messages")?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+
+                    guilds
+                        .entry(guild_id)
+                        .or_default()
+                        .flagged_messages
+                        .entry(ChannelId::new(from_sql(row.get(1)?)))
+                        .or_default()
+                        .insert(MessageId::new(from_sql(row.get(2)?)));
+                }
+
+                let mut stmt =
+                    conn.prepare("SELECT guild_id, channel_id, user_id FROM collaborators")?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+
+                    guilds
+                        .entry(guild_id)
+                        .or_default()
+                        .collaborators
+                        .entry(ChannelId::new(from_sql(row.get(1)?)))
+                        .or_default()
+                        .insert(UserId::new(from_sql(row.get(2)?)));
+                }
+
+                let mut stmt = conn
+                    .prepare("SELECT guild_id, draw, ran_at, outcome FROM draw_log ORDER BY rowid")?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+                    let ran_at = DateTime::from_timestamp_millis(row.get(2)?)
+                        .ok_or_else(|| eyre!("invalid draw timestamp"))?;
+
+                    guilds
+                        .entry(guild_id)
+                        .or_default()
+                        .draw_log
+                        .push_back(DrawLogEntry {
+                            draw: row.get(1)?,
+                            ran_at,
+                            outcome: row.get::<_, String>(3)?.parse()?,
+                        });
+                }
+
+                let mut stmt = conn.prepare(
+                    "SELECT guild_id, message_id, count, last_drawn_at FROM message_stats",
+                )?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+                    let last_drawn_at = DateTime::from_timestamp_millis(row.get(3)?)
+                        .ok_or_else(|| eyre!("invalid draw timestamp"))?;
+
+                    guilds.entry(guild_id).or_default().message_stats.insert(
+                        MessageId::new(from_sql(row.get(1)?)),
+                        MessageStats {
+                            count: row.get(2)?,
+                            last_drawn_at,
+                        },
+                    );
+                }
+
+                Ok(Some(guilds))
+            })
+        })
+    }
+
+    fn save_state<'a>(
+        &'a self,
+        guilds: &'a HashMap<GuildId, GuildState>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut saved = self
+                .saved_state
+                .lock()
+                .map_err(|_| eyre!("saved state lock was poisoned"))?;
+
+            let mut tables = Vec::with_capacity(STATE_TABLES.len());
+
+            self.transaction(|tx| {
+                for (i, table) in STATE_TABLES.iter().enumerate() {
+                    let mut rows = Vec::new();
+                    for (&guild_id, guild) in guilds {
+                        rows.extend((table.rows)(to_sql(guild_id), guild)?);
+                    }
+
+                    // the first save rewrites the table, which also drops
+                    // anything left behind by older versions
+                    let previous = match saved.as_ref() {
+                        Some(saved) => &saved[i],
+                        None => {
+                            tx.execute(&format!("DELETE FROM {}", table.name), [])?;
+                            &HashMap::new()
+                        }
+                    };
+
+                    table.save(tx, previous, &rows)?;
+                    tables.push(rows.into_iter().collect());
+                }
+
+                Ok(())
+            })?;
+
+            *saved = Some(tables);
+
+            Ok(())
+        })
+    }
+
+    fn load_index(&self) -> BoxFuture<'_, Result<Option<HashMap<GuildId, GuildIndex>>>> {
+        Box::pin(async move {
+            self.with_conn(|conn| {
+                let mut guilds = HashMap::<GuildId, GuildIndex>::new();
+
+                let mut stmt = conn.prepare(
+                    "SELECT guild_id, channel_id, message_id, meta FROM messages ORDER BY message_id",
+                )?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+                    let meta = row
+                        .get::<_, Option<String>>(3)?
+                        .map(|meta| serde_json::from_str(&meta))
+                        .transpose()
+                        .wrap_err("failed to parse message metadata")?;
+
+                    guilds
+                        .entry(guild_id)
+                        .or_default()
+                        .messages
+                        .entry(ChannelId::new(from_sql(row.get(1)?)))
+                        .or_default()
+                        .push(IndexedMessage {
+                            id: MessageId::new(from_sql(row.get(2)?)),
+                            meta,
+                        });
+                }
+
+                let mut stmt =
+                    conn.prepare("SELECT guild_id, channel_id, message_id FROM last_indexed")?;
+                let mut rows = stmt.query([])?;
+                while let Some(row) = rows.next()? {
+                    let guild_id = GuildId::new(from_sql(row.get(0)?));
+
+                    guilds.entry(guild_id).or_default().last_indexed.insert(
+                        ChannelId::new(from_sql(row.get(1)?)),
+                        MessageId::new(from_sql(row.get(2)?)),
+                    );
+                }
+
+                if guilds.is_empty() {
+                    return Ok(None);
+                }
+
+                Ok(Some(guilds))
+            })
+        })
+    }
+
+    fn save_index<'a>(
+        &'a self,
+        guilds: &'a HashMap<GuildId, GuildIndex>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.transaction(|tx| {
+                tx.execute_batch("DELETE FROM messages; DELETE FROM last_indexed;")?;
+
+                let mut insert_message = tx.prepare(
+                    "INSERT OR IGNORE INTO messages (guild_id, channel_id, message_id, author_id, meta) VALUES (?1, ?2, ?3, ?4, ?5)",
+                )?;
+                let mut insert_last_indexed = tx.prepare(
+                    "INSERT INTO last_indexed (guild_id, channel_id, message_id) VALUES (?1, ?2, ?3)",
+                )?;
+
+                for (&guild_id, guild) in guilds {
+                    for (&channel_id, messages) in &guild.messages {
+                        for message in messages {
+                            let (author_id, meta) = meta_columns(message.meta.as_ref())?;
+
+                            insert_message.execute(params![
+                                to_sql(guild_id),
+                                to_sql(channel_id),
+                                to_sql(message.id),
+                                author_id,
+                                meta,
+                            ])?;
+                        }
+                    }
+
+                    for (&channel_id, &message_id) in &guild.last_indexed {
+                        insert_last_indexed.execute(params![
+                            to_sql(guild_id),
+                            to_sql(channel_id),
+                            to_sql(message_id)
+                        ])?;
+                    }
+                }
+
+                Ok(())
+            })
+        })
+    }
+
+    fn record_index_event<'a>(
+        &'a self,
+        event: &'a IndexEvent,
+        guilds: &'a HashMap<GuildId, GuildIndex>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.transaction(|tx| {
+                match *event {
+                    IndexEvent::Add {
+                        guild_id,
+                        channel_id,
+                        message_id,
+                        ref meta,
+                    } => {
+                        let (author_id, meta) = meta_columns(meta.as_ref())?;
+
+                        tx.execute(
+                            "INSERT INTO messages (guild_id, channel_id, message_id, author_id, meta) VALUES (?1, ?2, ?3, ?4, ?5)
+                             ON CONFLICT (channel_id, message_id) DO UPDATE SET author_id = COALESCE(excluded.author_id, author_id), meta = COALESCE(excluded.meta, meta)",
+                            params![to_sql(guild_id), to_sql(channel_id), to_sql(message_id), author_id, meta],
+                        )?;
+
+                        sync_last_indexed(tx, guilds, guild_id, channel_id)?;
+                    }
+                    IndexEvent::Update {
+                        guild_id: _,
+                        channel_id,
+                        message_id,
+                        ref meta,
+                    } => {
+                        let (author_id, meta) = meta_columns(Some(meta))?;
+
+                        tx.execute(
+                            "UPDATE messages SET author_id = ?3, meta = ?4 WHERE channel_id = ?1 AND message_id = ?2",
+                            params![to_sql(channel_id), to_sql(message_id), author_id, meta],
+                        )?;
+                    }
+                    IndexEvent::Remove {
+                        guild_id,
+                        channel_id,
+                        message_id,
+                    } => {
+                        tx.execute(
+                            "DELETE FROM messages WHERE channel_id = ?1 AND message_id = ?2",
+                            params![to_sql(channel_id), to_sql(message_id)],
+                        )?;
+
+                        sync_last_indexed(tx, guilds, guild_id, channel_id)?;
+                    }
+                    IndexEvent::RemoveChannel {
+                        guild_id: _,
+                        channel_id,
+                    } => {
+                        tx.execute(
+                            "DELETE FROM messages WHERE channel_id = ?1",
+                            params![to_sql(channel_id)],
+                        )?;
+                        tx.execute(
+                            "DELETE FROM last_indexed WHERE channel_id = ?1",
+                            params![to_sql(channel_id)],
+                        )?;
+                    }
+                    IndexEvent::LastIndexed {
+                        guild_id,
+                        channel_id,
+                        message_id: _,
+                    } => {
+                        sync_last_indexed(tx, guilds, guild_id, channel_id)?;
+                    }
+                }
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -40,7 +40,7 @@ async fn handle_error_inner(err: FrameworkError<'_>) -> Result<()> {
     Ok(())
 }
 
-pub fn handle_error(err: FrameworkError<'_>) -> BoxFuture<()> {
+pub fn handle_error(err: FrameworkError<'_>) -> BoxFuture<'_, ()> {
     Box::pin(async move {
         if let Err(err) = handle_error_inner(err).await {
             error!("error handling error: {:?}", err);
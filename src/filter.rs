@@ -0,0 +1,254 @@
+//! Rules for which messages are eligible for random draws.
+//!
+//! Messages by bots and messages that aren't regular messages or replies are
+//! never eligible. On top of that, [`Filters`] can exclude messages based on
+//! their content, attachments and age.
+//!
+//! Filters are applied when a message is indexed and again when it is drawn,
+//! since the filters can change and messages age after they are indexed.
+
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Duration, Utc};
+use color_eyre::eyre::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use serenity::all::{Message, MessageType};
+
+use crate::data::index::IndexedMessage;
+
+/// The configurable rules for which messages are eligible for random draws
+#[serde_as]
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Filters {
+    /// The minimum number of characters in the message content. Zero allows
+    /// messages without content, e.g. only attachments
+    #[serde(default)]
+    pub min_length: u32,
+    /// Whether messages must or must not have attachments
+    #[serde(default)]
+    pub attachments: AttachmentFilter,
+    /// Exclude messages that consist of nothing but links
+    #[serde(default)]
+    pub exclude_links_only: bool,
+    /// Exclude messages with spoilered text or attachments
+    #[serde(default)]
+    pub exclude_spoilers: bool,
+    /// Exclude messages with polls
+    #[serde(default)]
+    pub exclude_polls: bool,
+    /// Exclude messages whose content matches any of these patterns
+    #[serde(default)]
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    pub exclude_patterns: Vec<Regex>,
+    /// Exclude messages older than this many days
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+}
+
+/// Whether messages must or must not have attachments to be eligible
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentFilter {
+    /// Messages are eligible either way
+    #[default]
+    Any,
+    /// Only messages with attachments are eligible
+    Require,
+    /// Only messages without attachments are eligible
+    Exclude,
+}
+
+impl FromStr for AttachmentFilter {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "any" => Ok(AttachmentFilter::Any),
+            "require" => Ok(AttachmentFilter::Require),
+            "exclude" => Ok(AttachmentFilter::Exclude),
+            _ => Err(color_eyre::eyre::eyre!(
+                "attachments must be one of `any`, `require` or `exclude`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for AttachmentFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AttachmentFilter::Any => "any",
+            AttachmentFilter::Require => "require",
+            AttachmentFilter::Exclude => "exclude",
+        })
+    }
+}
+
+/// A reason why a message isn't eligible for random draws
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    Bot,
+    /// The message is e.g. a pin notification or a thread starter
+    Kind,
+    TooShort {
+        length: u32,
+        min_length: u32,
+    },
+    MissingAttachments,
+    HasAttachments,
+    OnlyLinks,
+    Spoiler,
+    Poll,
+    /// The content matches the pattern with this source
+    Pattern(String),
+    TooOld {
+        max_age_days: u32,
+    },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::Bot => f.write_str("it was posted by a bot"),
+            Rejection::Kind => f.write_str("it is not a regular message or a reply"),
+            Rejection::TooShort { length, min_length } => write!(
+                f,
+                "it is {} characters long, shorter than the minimum of {}",
+                length, min_length
+            ),
+            Rejection::MissingAttachments => f.write_str("it has no attachments"),
+            Rejection::HasAttachments => f.write_str("it has attachments"),
+            Rejection::OnlyLinks => f.write_str("it only contains links"),
+            Rejection::Spoiler => f.write_str("it contains spoilers"),
+            Rejection::Poll => f.write_str("it is a poll"),
+            Rejection::Pattern(pattern) => write!(f, "it matches the pattern `{}`", pattern),
+            Rejection::TooOld { max_age_days } => {
+                write!(f, "it is older than {} days", max_age_days)
+            }
+        }
+    }
+}
+
+impl Filters {
+    /// Every reason why `message` isn't eligible for random draws at `now`.
+    /// The message is eligible if there are none
+    pub fn rejections(&self, message: &Message, now: DateTime<Utc>) -> Vec<Rejection> {
+        let mut rejections = Vec::new();
+
+        if message.author.bot {
+            rejections.push(Rejection::Bot);
+        }
+
+        if !matches!(
+            message.kind,
+            MessageType::Regular | MessageType::InlineReply
+        ) {
+            rejections.push(Rejection::Kind);
+        }
+
+        let length = message.content.chars().count() as u32;
+        if length < self.min_length {
+            rejections.push(Rejection::TooShort {
+                length,
+                min_length: self.min_length,
+            });
+        }
+
+        match self.attachments {
+            AttachmentFilter::Require if message.attachments.is_empty() => {
+                rejections.push(Rejection::MissingAttachments)
+            }
+            AttachmentFilter::Exclude if !message.attachments.is_empty() => {
+                rejections.push(Rejection::HasAttachments)
+            }
+            _ => {}
+        }
+
+        if self.exclude_links_only && is_only_links(message) {
+            rejections.push(Rejection::OnlyLinks);
+        }
+
+        if self.exclude_spoilers && has_spoilers(message) {
+            rejections.push(Rejection::Spoiler);
+        }
+
+        if self.exclude_polls && is_poll(message) {
+            rejections.push(Rejection::Poll);
+        }
+
+        rejections.extend(
+            self.exclude_patterns
+                .iter()
+                .filter(|pattern| pattern.is_match(&message.content))
+                .map(|pattern| Rejection::Pattern(pattern.to_string())),
+        );
+
+        if let Some(max_age_days) = self.max_age_days {
+            if *message.timestamp < now - Duration::days(max_age_days.into()) {
+                rejections.push(Rejection::TooOld { max_age_days });
+            }
+        }
+
+        rejections
+    }
+
+    /// Check if `message` is eligible for random draws at `now`
+    pub fn allows(&self, message: &Message, now: DateTime<Utc>) -> bool {
+        self.rejections(message, now).is_empty()
+    }
+
+    /// Check the rules that can be checked without fetching the message, using
+    /// its stored metadata. Messages without metadata are only checked for
+    /// their age
+    pub fn allows_indexed(&self, message: &IndexedMessage, now: DateTime<Utc>) -> bool {
+        let recent_enough = self.max_age_days.is_none_or(|max_age_days| {
+            *message.id.created_at() >= now - Duration::days(max_age_days.into())
+        });
+
+        recent_enough
+            && message.meta.as_ref().is_none_or(|meta| {
+                meta.content_len >= self.min_length
+                    && match self.attachments {
+                        AttachmentFilter::Any => true,
+                        AttachmentFilter::Require => meta.attachment_count > 0,
+                        AttachmentFilter::Exclude => meta.attachment_count == 0,
+                    }
+            })
+    }
+}
+
+/// Check if a message has content that consists of nothing but links and no
+/// attachments
+fn is_only_links(message: &Message) -> bool {
+    let mut words = message.content.split_whitespace().peekable();
+
+    message.attachments.is_empty()
+        && words.peek().is_some()
+        && words.all(|word| {
+            // links wrapped in angle brackets don't show an embed
+            let word = word.trim_start_matches('<').trim_end_matches('>');
+
+            word.starts_with("https://") || word.starts_with("http://")
+        })
+}
+
+fn has_spoilers(message: &Message) -> bool {
+    // `||spoiler||` splits into at least three parts
+    message.content.split("||").nth(2).is_some()
+        || message
+            .attachments
+            .iter()
+            .any(|attachment| attachment.filename.starts_with("SPOILER_"))
+}
+
+fn is_poll(message: &Message) -> bool {
+    // serenity doesn't expose polls until 0.12.3 (see serenity#2892), but a
+    // message can't be empty otherwise, so a message with no content,
+    // attachments, embeds or stickers must be a poll. this relies on the
+    // message content intent
+    message.content.is_empty()
+        && message.attachments.is_empty()
+        && message.embeds.is_empty()
+        && message.sticker_items.is_empty()
+}
@@ -1,12 +1,99 @@
 use color_eyre::eyre::{Result, WrapErr};
 use poise::BoxFuture;
-use serenity::all::{ChannelId, Context, FullEvent, GuildChannel, Message, MessageId};
+use serenity::all::{
+    ChannelId, Context, CreateMessage, FullEvent, GuildChannel, GuildId, Mentionable, Message,
+    MessageId, Reaction, ReactionType,
+};
 
 use crate::{
-    data::{Data, FrameworkContext},
+    data::{
+        config::{Enforcement, MonologuesConfig},
+        Data, FrameworkContext,
+    },
     utils, STARTUP_TIME,
 };
 
+/// The reaction authors add to their own messages to exclude them from draws
+pub const EXCLUDE_REACTION: &str = "🚫";
+
+/// The reaction added to messages posted in someone else's monologue channel
+/// when they are flagged instead of deleted
+pub const FLAG_REACTION: &str = "🚩";
+
+/// Flag or delete a message posted in someone else's monologue channel, and
+/// tell its author why if configured to
+async fn enforce(
+    ctx: &Context,
+    data: &Data,
+    guild_id: GuildId,
+    config: &MonologuesConfig,
+    msg: &Message,
+) -> Result<()> {
+    let action = match config.enforcement {
+        Enforcement::Off => return Ok(()),
+        Enforcement::Flag => {
+            msg.react(ctx, ReactionType::Unicode(FLAG_REACTION.to_string()))
+                .await
+                .wrap_err("failed to flag foreign message")?;
+
+            // flagging the message keeps it from being drawn even if it ends
+            // up in the index, e.g. after ownership is no longer enforced.
+            // unlike an exclusion, its author can't undo this
+            data.state
+                .lock()
+                .await
+                .flag_message(guild_id, msg.channel_id, msg.id)
+                .await
+                .wrap_err("failed to remember flagged message")?;
+
+            "was flagged"
+        }
+        Enforcement::Delete => {
+            msg.delete(ctx)
+                .await
+                .wrap_err("failed to delete foreign message")?;
+
+            "was deleted"
+        }
+    };
+
+    debug!(
+        message_id = ?msg.id,
+        author_id = ?msg.author.id,
+        ?config.enforcement,
+        "enforced monologue channel ownership"
+    );
+
+    if !config.notify_foreign {
+        return Ok(());
+    }
+
+    let mut content = format!(
+        "Your message in {} {} because monologue channels are only for their owner's posts.",
+        msg.channel_id.mention(),
+        action,
+    );
+
+    if config.allow_replies {
+        content.push_str(" You can reply to their messages instead.");
+    }
+
+    if config.enforcement == Enforcement::Delete {
+        content.push_str(&format!("\n\n>>> {}", msg.content));
+    }
+
+    // users can turn off direct messages, which is no reason to fail
+    if let Err(err) = msg
+        .author
+        .direct_message(ctx, CreateMessage::new().content(content))
+        .await
+    {
+        debug!("failed to notify author of foreign message: {}", err);
+    }
+
+    Ok(())
+}
+
 async fn message(ctx: &Context, data: &Data, msg: &Message) -> Result<()> {
     // ignore messages sent before the bot started up. since we always index on
     // startup, we already know about these messages and skipping them prevents
@@ -15,25 +102,49 @@ async fn message(ctx: &Context, data: &Data, msg: &Message) -> Result<()> {
         return Ok(());
     }
 
+    // monologue channels only exist in guilds
+    let Some(guild_id) = msg.guild_id else {
+        return Ok(());
+    };
+
     // should_track checks if this channel is a monologue channel and we only
     // care about messages sent to monologue channels
-    if !data.state.lock().await.should_track(msg.channel_id) {
+    if !data
+        .state
+        .lock()
+        .await
+        .should_track(guild_id, msg.channel_id)
+    {
         return Ok(());
     }
 
     let channel_id = msg.channel_id;
+    let guild_config = data.guild_config(guild_id).await;
+
+    // foreign posts are kept out of draws once ownership is enforced, since
+    // they'd otherwise be drawn as the owner's monologue
+    let is_foreign = data
+        .state
+        .lock()
+        .await
+        .ownership(guild_id, &guild_config.monologues)
+        .is_foreign(msg);
+
+    if is_foreign {
+        return enforce(ctx, data, guild_id, &guild_config.monologues, msg).await;
+    }
 
     // save the message to the index. this function internally checks if the
     // message is valid and will do nothing if it is not
     data.index
         .lock()
         .await
-        .save_message(msg)
+        .save_message(guild_id, msg, &guild_config.filters)
         .await
         .wrap_err("failed to save message")?;
 
     // if autosort is enabled, trigger the channel sorting mechanism
-    if data.config.is_autosort_enabled() {
+    if guild_config.is_autosort_enabled() {
         utils::move_channel_to_top(ctx, data, channel_id)
             .await
             .wrap_err("failed to move channel to top")?;
@@ -42,15 +153,116 @@ async fn message(ctx: &Context, data: &Data, msg: &Message) -> Result<()> {
     Ok(())
 }
 
+async fn message_update(
+    ctx: &Context,
+    data: &Data,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    message_id: MessageId,
+) -> Result<()> {
+    let Some(guild_id) = guild_id else {
+        return Ok(());
+    };
+
+    if !data.state.lock().await.should_track(guild_id, channel_id) {
+        return Ok(());
+    }
+
+    // the update event only contains the fields that changed, so we fetch the
+    // full message to validate it the same way as a new message
+    let msg = channel_id
+        .message(ctx, message_id)
+        .await
+        .wrap_err("failed to fetch edited message")?;
+
+    let guild_config = data.guild_config(guild_id).await;
+
+    // foreign posts are never indexed, see `message`
+    if data
+        .state
+        .lock()
+        .await
+        .ownership(guild_id, &guild_config.monologues)
+        .is_foreign(&msg)
+    {
+        return Ok(());
+    }
+
+    let filters = &guild_config.filters;
+
+    data.index
+        .lock()
+        .await
+        .refresh_message(guild_id, &msg, filters)
+        .await
+        .wrap_err("failed to refresh edited message")?;
+
+    Ok(())
+}
+
+/// Exclude a message from draws when its author reacts to it with
+/// [`EXCLUDE_REACTION`], or include it again when they remove the reaction.
+async fn reaction(ctx: &Context, data: &Data, reaction: &Reaction, added: bool) -> Result<()> {
+    let (Some(guild_id), Some(user_id)) = (reaction.guild_id, reaction.user_id) else {
+        return Ok(());
+    };
+
+    if !matches!(&reaction.emoji, ReactionType::Unicode(emoji) if emoji == EXCLUDE_REACTION) {
+        return Ok(());
+    }
+
+    if !data
+        .state
+        .lock()
+        .await
+        .should_track(guild_id, reaction.channel_id)
+    {
+        return Ok(());
+    }
+
+    // only the author decides whether their message is drawn
+    let message = reaction
+        .message(ctx)
+        .await
+        .wrap_err("failed to fetch reacted message")?;
+
+    if message.author.id != user_id {
+        return Ok(());
+    }
+
+    let changed = data
+        .state
+        .lock()
+        .await
+        .set_message_excluded(guild_id, reaction.channel_id, reaction.message_id, added)
+        .await
+        .wrap_err("failed to update excluded messages")?;
+
+    if changed {
+        debug!(
+            message_id = ?reaction.message_id,
+            excluded = added,
+            "author changed whether their message is excluded from draws"
+        );
+    }
+
+    Ok(())
+}
+
 async fn delete_messages_inner(
     data: &Data,
+    guild_id: Option<GuildId>,
     channel_id: &ChannelId,
     deleted_message_ids: impl IntoIterator<Item = &MessageId>,
 ) -> Result<()> {
     // same concept as above just with a bulk delete
 
+    let Some(guild_id) = guild_id else {
+        return Ok(());
+    };
+
     // we only care about monologue channels
-    if !data.state.lock().await.should_track(*channel_id) {
+    if !data.state.lock().await.should_track(guild_id, *channel_id) {
         return Ok(());
     }
 
@@ -60,9 +272,18 @@ async fn delete_messages_inner(
         data.index
             .lock()
             .await
-            .remove_message(*channel_id, *deleted_message_id)
+            .remove_message(guild_id, *channel_id, *deleted_message_id)
             .await
             .wrap_err("failed to remove message")?;
+
+        // a deleted message can't be drawn anymore, so there's no need to
+        // remember whether it was excluded or how often it was drawn
+        data.state
+            .lock()
+            .await
+            .forget_message(guild_id, *channel_id, *deleted_message_id)
+            .await
+            .wrap_err("failed to forget deleted message")?;
     }
 
     Ok(())
@@ -71,21 +292,21 @@ async fn delete_messages_inner(
 async fn channel_delete(data: &Data, channel: &GuildChannel) -> Result<()> {
     let mut state_lock = data.state.lock().await;
 
-    if !state_lock.should_track(channel.id) {
+    if !state_lock.should_track(channel.guild_id, channel.id) {
         return Ok(());
     }
 
     // we need to remove the channel from both state and index to prevent random
     // draws and indexing from failing
     state_lock
-        .remove_channel(channel.id)
+        .remove_channel(channel.guild_id, channel.id)
         .await
         .wrap_err("failed to remove channel")?;
 
     data.index
         .lock()
         .await
-        .remove_channel(channel.id)
+        .remove_channel(channel.guild_id, channel.id)
         .await
         .wrap_err("failed to remove channel")?;
 
@@ -101,18 +322,36 @@ pub fn event_handler<'a>(
     Box::pin(async move {
         match event {
             FullEvent::Message { new_message } => message(ctx, data, new_message).await?,
+            FullEvent::MessageUpdate { event, .. } => {
+                message_update(ctx, data, event.guild_id, event.channel_id, event.id).await?
+            }
+            FullEvent::ReactionAdd { add_reaction } => {
+                reaction(ctx, data, add_reaction, true).await?
+            }
+            FullEvent::ReactionRemove { removed_reaction } => {
+                reaction(ctx, data, removed_reaction, false).await?
+            }
             FullEvent::MessageDelete {
                 channel_id,
                 deleted_message_id,
-                guild_id: _,
+                guild_id,
             } => {
-                delete_messages_inner(data, channel_id, std::iter::once(deleted_message_id)).await?
+                delete_messages_inner(
+                    data,
+                    *guild_id,
+                    channel_id,
+                    std::iter::once(deleted_message_id),
+                )
+                .await?
             }
             FullEvent::MessageDeleteBulk {
                 channel_id,
                 multiple_deleted_messages_ids,
-                guild_id: _,
-            } => delete_messages_inner(data, channel_id, multiple_deleted_messages_ids).await?,
+                guild_id,
+            } => {
+                delete_messages_inner(data, *guild_id, channel_id, multiple_deleted_messages_ids)
+                    .await?
+            }
             FullEvent::ChannelDelete {
                 channel,
                 messages: _,
@@ -1,9 +1,10 @@
-use std::sync::OnceLock;
+use std::{collections::HashMap, sync::OnceLock};
 
 use chrono::{DateTime, Utc};
 use color_eyre::eyre::Result;
 use poise::{Framework, FrameworkOptions};
 use random_draw::random_draw_task;
+use reconcile::reconcile_task;
 use serenity::all::{ActivityData, ClientBuilder, GatewayIntents};
 use tracing_subscriber::prelude::*;
 
@@ -13,8 +14,12 @@ extern crate tracing;
 pub mod command;
 pub mod data;
 pub mod error;
+pub mod filter;
 pub mod handler;
+pub mod ownership;
 pub mod random_draw;
+pub mod reconcile;
+pub mod template;
 pub mod utils;
 
 /// A global lock for the startup time of the bot. Useful for checking if the
@@ -49,7 +54,9 @@ async fn main() -> Result<()> {
         // allow deleting messages of users posting outside their channel
         | GatewayIntents::GUILD_MESSAGES
         // allow reading messages in monologue channels
-        | GatewayIntents::MESSAGE_CONTENT;
+        | GatewayIntents::MESSAGE_CONTENT
+        // allow authors to exclude their messages from draws with a reaction
+        | GatewayIntents::GUILD_MESSAGE_REACTIONS;
 
     debug!(?intents, "Starting bot");
     let framework = Framework::builder()
@@ -68,12 +75,35 @@ async fn main() -> Result<()> {
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
 
                 trace!("running startup index");
-                let channels = data.state.lock().await.get_channels();
-                data.index.lock().await.index(ctx, channels).await?;
+                let channels = data.state.lock().await.get_all_channels();
+
+                let guilds = data.state.lock().await.get_guilds();
+                let mut filters = HashMap::new();
+                let mut ownership = HashMap::new();
+                for guild_id in guilds {
+                    let guild_config = data.guild_config(guild_id).await;
+                    let guild_ownership = data
+                        .state
+                        .lock()
+                        .await
+                        .ownership(guild_id, &guild_config.monologues);
+
+                    filters.insert(guild_id, guild_config.filters.clone());
+                    ownership.insert(guild_id, guild_ownership);
+                }
+
+                data.index
+                    .lock()
+                    .await
+                    .index(ctx, channels, &filters, &ownership)
+                    .await?;
 
                 // start the random draw task
                 tokio::task::spawn(random_draw_task(data.clone(), ctx.http.clone()));
 
+                // start the index reconciliation task
+                tokio::task::spawn(reconcile_task(data.clone(), ctx.http.clone()));
+
                 // :eyes:
                 ctx.set_activity(Some(ActivityData::watching("you shitpost")));
 
@@ -0,0 +1,89 @@
+//! Rules for who may post in a monologue channel.
+//!
+//! Besides its owner, collaborators and members with the guest role may post
+//! in a monologue channel, as may anyone replying to a message in it if
+//! replies are allowed. Everything else is a foreign post, which is flagged or
+//! deleted as it is posted and never indexed, depending on the
+//! [`Enforcement`].
+
+use std::collections::{HashMap, HashSet};
+
+use serenity::all::{ChannelId, Message, MessageType, RoleId, UserId};
+
+use crate::data::config::{Enforcement, MonologuesConfig};
+
+/// Who may post in each monologue channel of a guild. See
+/// [`State::ownership`](crate::data::state::State::ownership).
+#[derive(Debug, Clone, Default)]
+pub struct Ownership {
+    pub enforcement: Enforcement,
+    pub guest_role_id: Option<RoleId>,
+    pub allow_replies: bool,
+    pub owners: HashMap<ChannelId, UserId>,
+    pub collaborators: HashMap<ChannelId, HashSet<UserId>>,
+}
+
+impl Ownership {
+    pub fn new(
+        config: &MonologuesConfig,
+        owners: HashMap<ChannelId, UserId>,
+        collaborators: HashMap<ChannelId, HashSet<UserId>>,
+    ) -> Self {
+        Self {
+            enforcement: config.enforcement,
+            guest_role_id: config.guest_role_id,
+            allow_replies: config.allow_replies,
+            owners,
+            collaborators,
+        }
+    }
+
+    /// Check if `msg` was posted in someone else's monologue channel without
+    /// being allowed to. Nothing is foreign unless ownership is enforced
+    pub fn is_foreign(&self, msg: &Message) -> bool {
+        if self.enforcement == Enforcement::Off {
+            return false;
+        }
+
+        // system messages like pin notifications are posted on behalf of
+        // whoever caused them, which may well be someone else
+        if !matches!(msg.kind, MessageType::Regular | MessageType::InlineReply) {
+            return false;
+        }
+
+        // bots are never drawn anyway, and other bots may well be posting on
+        // purpose
+        if msg.author.bot {
+            return false;
+        }
+
+        let Some(&owner_id) = self.owners.get(&msg.channel_id) else {
+            return false;
+        };
+
+        let is_collaborator = self
+            .collaborators
+            .get(&msg.channel_id)
+            .is_some_and(|collaborators| collaborators.contains(&msg.author.id));
+
+        if msg.author.id == owner_id || is_collaborator {
+            return false;
+        }
+
+        // messages fetched from Discord rather than received as they are
+        // posted don't include the roles of their author, so they are given
+        // the benefit of the doubt
+        let is_guest = self.guest_role_id.is_some_and(|role_id| match &msg.member {
+            Some(member) => member.roles.contains(&role_id),
+            None => true,
+        });
+
+        let is_reply = msg.kind == MessageType::InlineReply
+            && msg
+                .message_reference
+                .as_ref()
+                .is_some_and(|reference| reference.channel_id == msg.channel_id);
+
+        !is_guest && !(self.allow_replies && is_reply)
+    }
+}
@@ -1,101 +1,630 @@
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
-use chrono::{Duration, Utc};
-use color_eyre::eyre::{Context, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use color_eyre::eyre::{bail, Context, Result};
 use rand::seq::SliceRandom;
-use serenity::all::{CreateMessage, Http};
+use serenity::all::{ChannelId, CreateMessage, GuildId, Http, Message, MessageId};
 
 use crate::{
-    data::{config::RandomDrawConfig, Data},
+    data::{
+        config::{CatchUpPolicy, FallbackPolicy, RandomDrawConfig, TimeRange},
+        index::Index,
+        state::{DrawOutcome, DrawRecord, DrawSource},
+        Data,
+    },
+    template::{Template, TemplateContext, Variable},
     utils,
 };
 
+/// Supervises the random draw tasks of every guild.
+///
+/// Each random draw of each guild with state gets its own task. New tasks are
+/// spawned whenever [`DataInner::reschedule`](crate::data::DataInner::reschedule)
+/// is notified.
 pub async fn random_draw_task(data: Data, http: Arc<Http>) {
-    let Some(random_draw) = &data.config.random_draw else {
-        debug!("random draw is disabled");
-        return;
-    };
+    debug!("starting random draw supervisor");
 
-    debug!("starting random draw task");
+    let mut tasks = HashSet::new();
 
-    let tz = *random_draw.timezone;
+    loop {
+        // register interest before reading the guilds so a notification sent
+        // in between is not lost
+        let notified = data.reschedule.notified();
 
-    // shorthand for getting the current time in the configured timezone
-    let now = || Utc::now().with_timezone(&tz);
-    // shorthand for getting the next scheduled time in the configured timezone.
-    // it is possible to make schedules that have no future times so in that
-    // case we just wait 24h
-    let next = || {
-        random_draw.schedule.upcoming(tz).next().unwrap_or_else(|| {
-            warn!("cron schedule produced no upcoming times! falling back to now + 24h");
+        let guilds = data.state.lock().await.get_guilds();
 
-            now() + Duration::days(1)
-        })
-    };
+        for guild_id in guilds {
+            for draw in &data.guild_config(guild_id).await.draws {
+                if tasks.insert((guild_id, draw.name.clone())) {
+                    tokio::task::spawn(guild_random_draw_task(
+                        data.clone(),
+                        http.clone(),
+                        guild_id,
+                        draw.name.clone(),
+                    ));
+                }
+            }
+        }
+
+        notified.await;
+    }
+}
+
+/// Runs a single random draw of a single guild on its configured schedule.
+///
+/// The guild's config is resolved again after every draw and whenever the
+/// schedule might have changed, so edits made with `/config` apply right away.
+async fn guild_random_draw_task(data: Data, http: Arc<Http>, guild_id: GuildId, name: String) {
+    debug!(?guild_id, name, "starting random draw task");
+
+    let mut checked_missed = false;
 
     loop {
-        let now = now();
-        let next = next();
-        trace!("next random draw at {:?}", next);
+        let notified = data.reschedule.notified();
+
+        let Some(random_draw) = data.guild_config(guild_id).await.draw(&name).cloned() else {
+            debug!(?guild_id, name, "random draw is disabled");
+            notified.await;
+            continue;
+        };
+
+        // a guild may inherit a draw channel belonging to another guild from
+        // the config file. we never want to post messages across guilds
+        match utils::guild_of(&http, random_draw.channel_id).await {
+            Ok(channel_guild) if channel_guild == guild_id => {}
+            Ok(_) => {
+                debug!(
+                    ?guild_id,
+                    name, "random draw channel belongs to another guild"
+                );
+                notified.await;
+                continue;
+            }
+            Err(err) => {
+                error!(
+                    ?guild_id,
+                    name, "failed to resolve the random draw channel: {:?}", err
+                );
+                notified.await;
+                continue;
+            }
+        }
+
+        // draws missed while the bot was offline are only made up once, when
+        // the task starts. afterwards the task runs every scheduled draw itself
+        if !checked_missed {
+            checked_missed = true;
+
+            catch_up(&random_draw, guild_id, &data, &http).await;
+        }
+
+        let tz = *random_draw.timezone;
+
+        let now = Utc::now().with_timezone(&tz);
+        // it is possible to make schedules that have no future times so in
+        // that case we just wait 24h
+        let next = random_draw.schedule.upcoming(tz).next().unwrap_or_else(|| {
+            warn!("cron schedule produced no upcoming times! falling back to now + 24h");
+
+            now + Duration::days(1)
+        });
+        trace!(?guild_id, name, "next random draw at {:?}", next);
 
         let sleep_duration = next.signed_duration_since(now);
-        debug!("sleeping for {}", sleep_duration);
+        debug!(?guild_id, name, "sleeping for {}", sleep_duration);
 
         // SAFETY: it is not possible for this to be negative because we get now
         // before next
         let duration_std = sleep_duration.to_std().unwrap();
-        tokio::time::sleep(duration_std).await;
 
-        if let Err(err) = do_random_draw(random_draw, &data, &http).await {
-            error!("failed to run random draw: {:?}", err);
+        tokio::select! {
+            _ = tokio::time::sleep(duration_std) => {}
+            _ = notified => {
+                trace!(?guild_id, name, "rescheduling random draw");
+                continue;
+            }
+        }
+
+        match do_random_draw(&random_draw, guild_id, &data, &http).await {
+            Ok(outcome) => debug!(?guild_id, name, ?outcome, "finished random draw"),
+            Err(err) => error!(?guild_id, name, "failed to run random draw: {:?}", err),
+        }
+    }
+}
+
+/// The most draws [`CatchUpPolicy::All`] makes up for at once, so a long
+/// downtime doesn't flood the draw channel
+const MAX_CATCH_UP_DRAWS: usize = 10;
+
+/// Run the draws that were scheduled between the last trigger and now
+/// according to the draw's [`CatchUpPolicy`].
+async fn catch_up(random_draw: &RandomDrawConfig, guild_id: GuildId, data: &Data, http: &Http) {
+    let name = random_draw.name.as_str();
+
+    if random_draw.catch_up == CatchUpPolicy::Skip {
+        return;
+    }
+
+    // a draw that never ran can't have missed anything
+    let Some(last_trigger) = data.state.lock().await.last_trigger(guild_id, name) else {
+        return;
+    };
+
+    let tz = *random_draw.timezone;
+    let now = Utc::now().with_timezone(&tz);
+
+    let slots = random_draw
+        .schedule
+        .after(&last_trigger.with_timezone(&tz))
+        .take_while(|time| *time <= now)
+        .map(|time| time.with_timezone(&Utc))
+        .collect::<Vec<_>>();
+    let missed = slots.len();
+
+    if missed == 0 {
+        return;
+    }
+
+    let draws = match random_draw.catch_up {
+        CatchUpPolicy::Skip => 0,
+        CatchUpPolicy::Once => 1,
+        CatchUpPolicy::All => missed.min(MAX_CATCH_UP_DRAWS),
+    };
+
+    if missed > MAX_CATCH_UP_DRAWS {
+        warn!(
+            ?guild_id,
+            name, "more than {} random draws were missed", MAX_CATCH_UP_DRAWS
+        );
+    }
+
+    info!(
+        ?guild_id,
+        name, missed, "random draws were missed since {}, running {} now", last_trigger, draws
+    );
+
+    // each make-up draw pools what was posted between its slot and the slot
+    // before it, starting from the last trigger. the first one also covers
+    // any slots beyond the limit, and the last one covers everything up to
+    // now because the next scheduled draw only pools what is posted after it
+    let mut ends = slots[missed - draws..].to_vec();
+    if let Some(last) = ends.last_mut() {
+        *last = Utc::now();
+    }
+
+    let mut after = last_trigger;
+    let mut posted = false;
+
+    for before in ends {
+        match draw(
+            random_draw,
+            guild_id,
+            data,
+            http,
+            Some((Some(after), Some(before))),
+        )
+        .await
+        {
+            Ok(outcome) => {
+                debug!(?guild_id, name, ?outcome, "finished catch up draw");
+                posted |= outcome != DrawOutcome::Skipped;
+
+                if let Err(err) = data
+                    .state
+                    .lock()
+                    .await
+                    .log_draw(guild_id, name, outcome)
+                    .await
+                {
+                    error!(?guild_id, name, "failed to log catch up draw: {:?}", err);
+                }
+            }
+            Err(err) => error!(?guild_id, name, "failed to run catch up draw: {:?}", err),
+        }
+
+        after = before;
+    }
+
+    // the trigger only moves once every make-up draw ran, since each draw
+    // would otherwise pool from the previous one's trigger instead of its slot
+    if posted {
+        if let Err(err) = data.state.lock().await.just_triggered(guild_id, name).await {
+            error!(
+                ?guild_id,
+                name, "failed to record catch up draws: {:?}", err
+            );
         }
     }
 }
 
+/// Run a random draw now, log its outcome and record it as triggered unless
+/// nothing was posted
 pub async fn do_random_draw(
     random_draw: &RandomDrawConfig,
+    guild_id: GuildId,
     data: &Data,
     http: impl AsRef<Http>,
-) -> Result<()> {
-    debug!("running random draw");
-    let http = http.as_ref();
+) -> Result<DrawOutcome> {
+    let outcome = draw(random_draw, guild_id, data, http.as_ref(), None).await?;
+
+    let mut state = data.state.lock().await;
+    state.log_draw(guild_id, &random_draw.name, outcome).await?;
+
+    if outcome != DrawOutcome::Skipped {
+        state.just_triggered(guild_id, &random_draw.name).await?;
+    }
+
+    Ok(outcome)
+}
+
+/// Run a random draw without recording it as triggered. The messages posted
+/// within `range` make up the pool of new messages if given, otherwise those
+/// posted since the last trigger do
+async fn draw(
+    random_draw: &RandomDrawConfig,
+    guild_id: GuildId,
+    data: &Data,
+    http: &Http,
+    range: Option<TimeRange>,
+) -> Result<DrawOutcome> {
+    let name = random_draw.name.as_str();
+    debug!(?guild_id, name, ?range, "running random draw");
+
+    let cooldown = random_draw.cooldown();
+    let collaborator_posts = data
+        .guild_config(guild_id)
+        .await
+        .monologues
+        .collaborator_posts;
 
-    let last_run = data.state.lock().await.last_trigger();
+    let (last_run, recent_draws, exclusions) = {
+        let state = data.state.lock().await;
+
+        (
+            state.last_trigger(guild_id, name),
+            state.recent_draws(guild_id, name, cooldown),
+            state.exclusions(guild_id, None, collaborator_posts),
+        )
+    };
     trace!(?last_run, "last random draw time");
-    let index = data.index.lock().await;
 
-    let message = match last_run {
-        Some(last_run) => index.random_message_since(last_run),
-        None => index.random_message(),
+    // messages that were reposted recently, and optionally their authors, are
+    // excluded to avoid repeating the same thing over and over
+    let recent_messages = recent_draws
+        .iter()
+        .map(|draw| draw.message_id)
+        .collect::<HashSet<_>>();
+    let recent_authors = recent_draws
+        .iter()
+        .filter(|_| random_draw.cooldown_authors)
+        .map(|draw| draw.author_id)
+        .collect::<HashSet<_>>();
+    trace!(?recent_messages, ?recent_authors, "random draw cooldown");
+
+    // the pools to try in order, each with the range of times messages must
+    // be posted in to be part of it. an "on this day" draw picks from
+    // messages posted on today's date in previous years, a draw with a window
+    // always draws from it, and any other draw picks from what was posted
+    // since the last draw. the first draw of the latter covers every message
+    // and doesn't need any fallback
+    let now = Utc::now();
+    let tz = *random_draw.timezone;
+    let today = now.with_timezone(&tz).date_naive();
+    let window = random_draw.window(now);
+    let filters = &data.guild_config(guild_id).await.filters;
+
+    let mut pools = if random_draw.on_this_day {
+        vec![(DrawSource::OnThisDay, (None, None))]
+    } else {
+        vec![(
+            DrawSource::New,
+            window.or(range).unwrap_or((last_run, None)),
+        )]
     };
-    trace!(?message, "random draw message");
 
-    let Some((channel_id, message_id)) = message else {
-        warn!("no messages found for random draw");
-        return Ok(());
+    if random_draw.on_this_day || window.is_some() || last_run.is_some() {
+        match random_draw.fallback {
+            FallbackPolicy::Skip | FallbackPolicy::Message => {}
+            FallbackPolicy::AllTime => pools.push((DrawSource::AllTime, (None, None))),
+            FallbackPolicy::Widening => {
+                pools.push((DrawSource::LastWeek, (Some(now - Duration::weeks(1)), None)));
+                pools.push((
+                    DrawSource::LastMonth,
+                    (Some(now - Duration::days(30)), None),
+                ));
+            }
+        }
+    }
+
+    let mut drawn = None;
+
+    for (source, (after, before)) in pools {
+        trace!(?source, ?after, ?before, "drawing from pool");
+
+        // messages indexed without metadata pass the author cooldown until
+        // they are fetched, which records their author so they don't pass
+        // again. the draw is repeated if one of them was by a recent author
+        let mut attempts = data.config.index.draw_attempts.max(1);
+
+        let msg = loop {
+            let msg = draw_message(guild_id, data, http, |index| {
+                index.random_message_weighted(
+                    guild_id,
+                    random_draw.strategy,
+                    random_draw.half_life_days,
+                    &exclusions,
+                    |channel_id, message| {
+                        let created_at = *message.id.created_at();
+
+                        let in_pool = if source == DrawSource::OnThisDay {
+                            is_on_this_day(created_at, today, tz)
+                        } else {
+                            after.is_none_or(|after| created_at >= after)
+                                && before.is_none_or(|before| created_at < before)
+                        };
+
+                        in_pool
+                            && filters.allows_indexed(message, now)
+                            && (random_draw.source_channels.is_empty()
+                                || random_draw.source_channels.contains(&channel_id))
+                            && !recent_messages.contains(&message.id)
+                            && !message
+                                .meta
+                                .as_ref()
+                                .is_some_and(|meta| recent_authors.contains(&meta.author_id))
+                    },
+                )
+            })
+            .await?;
+
+            match msg {
+                Some(msg) if recent_authors.contains(&msg.author.id) => {
+                    debug!(?msg.id, "drawn message is by a recent author");
+
+                    attempts -= 1;
+                    if attempts == 0 {
+                        break None;
+                    }
+                }
+                msg => break msg,
+            }
+        };
+
+        if let Some(msg) = msg {
+            drawn = Some((source, msg));
+            break;
+        }
+    }
+
+    let Some((source, msg)) = drawn else {
+        if random_draw.fallback != FallbackPolicy::Message {
+            warn!(?guild_id, name, fallback = %random_draw.fallback, "no messages found for random draw");
+            return Ok(DrawOutcome::Skipped);
+        }
+
+        info!(
+            ?guild_id,
+            name, "no messages found for random draw, sending the fallback message"
+        );
+
+        random_draw
+            .channel_id
+            .send_message(
+                http,
+                CreateMessage::new().content(&random_draw.fallback_message),
+            )
+            .await
+            .wrap_err("failed to send random draw fallback message")?;
+
+        return Ok(DrawOutcome::FallbackMessage);
     };
 
-    let msg = http
-        .get_message(channel_id, message_id)
-        .await
-        .wrap_err("failed to get random draw message")?;
+    info!(?guild_id, name, %source, "drew message {} for random draw", msg.id);
+
+    repost(random_draw, guild_id, data, http, msg, source).await?;
+
+    Ok(DrawOutcome::Drawn(source))
+}
+
+/// Repost a message to the channel of a random draw, prefixed with one of the
+/// draw's messages, and record it as a draw of that random draw so it counts
+/// towards the cooldown and the message's stats.
+pub async fn repost(
+    random_draw: &RandomDrawConfig,
+    guild_id: GuildId,
+    data: &Data,
+    http: &Http,
+    msg: Message,
+    source: DrawSource,
+) -> Result<()> {
+    let name = random_draw.name.as_str();
+    let tz = *random_draw.timezone;
+    let cooldown = random_draw.cooldown();
+
+    let draw = DrawRecord {
+        draw: name.to_string(),
+        channel_id: msg.channel_id,
+        message_id: msg.id,
+        author_id: msg.author.id,
+        drawn_at: Utc::now(),
+        source,
+    };
 
-    let prefix = random_draw
+    // pick from the messages whose conditions the drawn message meets
+    let now_local = Utc::now().with_timezone(&tz);
+    let candidates = random_draw
         .messages
-        .choose(&mut rand::thread_rng())
-        .map(|s| s.as_str());
+        .iter()
+        .filter(|message| message.matches(&msg, now_local))
+        .collect::<Vec<_>>();
+    let template = candidates
+        .choose_weighted(&mut rand::thread_rng(), |message| message.weight)
+        .ok()
+        .map(|message| message.template.clone());
+
+    let prefix = match template {
+        Some(template) => {
+            let draw_number = data.state.lock().await.draw_count(guild_id, name) + 1;
+
+            Some(render_template(&template, &msg, tz, Some(draw_number), http).await)
+        }
+        None => None,
+    };
     trace!(?prefix, "random draw prefix");
 
+    let uploads = utils::download_attachments(&msg, random_draw.reupload_limit_bytes()).await;
+    let repost = utils::format_repost(msg, prefix, random_draw.format, uploads);
+
     random_draw
         .channel_id
-        .send_message(
-            http,
-            CreateMessage::new().content(utils::format_repost_content(msg, prefix)),
-        )
+        .send_message(http, repost.into())
         .await
         .wrap_err("failed to send random draw message")?;
 
-    data.state.lock().await.just_triggered().await?;
+    data.state
+        .lock()
+        .await
+        .record_draw(guild_id, draw, cooldown)
+        .await?;
 
     Ok(())
 }
+
+/// Render a random draw message template for the drawn message, looking up
+/// the channel and guild names only if the template uses them.
+pub async fn render_template(
+    template: &Template,
+    message: &Message,
+    tz: Tz,
+    draw_number: Option<u64>,
+    http: &Http,
+) -> String {
+    let channel_name = if template.uses(Variable::ChannelName) {
+        message
+            .channel_id
+            .name(http)
+            .await
+            .inspect_err(|err| warn!(?err, "failed to get channel name for template"))
+            .ok()
+    } else {
+        None
+    };
+
+    let guild_name = match message.guild_id {
+        Some(guild_id) if template.uses(Variable::GuildName) => http
+            .get_guild(guild_id)
+            .await
+            .map(|guild| guild.name)
+            .inspect_err(|err| warn!(?err, "failed to get guild name for template"))
+            .ok(),
+        _ => None,
+    };
+
+    template.render(&TemplateContext {
+        message,
+        tz,
+        channel_name,
+        guild_name,
+        draw_number,
+    })
+}
+
+/// Check if a message created at `created_at` was posted on the same calendar
+/// date as `today` in a previous year, in the given timezone.
+///
+/// Messages from February 29th count as posted on February 28th in years that
+/// don't have a February 29th, so they still come up every year.
+pub fn is_on_this_day(created_at: DateTime<Utc>, today: NaiveDate, tz: Tz) -> bool {
+    let created = created_at.with_timezone(&tz).date_naive();
+
+    if created.year() >= today.year() {
+        return false;
+    }
+
+    let same_day = created.month() == today.month() && created.day() == today.day();
+    let leap_day_fallback = created.month() == 2
+        && created.day() == 29
+        && today.month() == 2
+        && today.day() == 28
+        && !today.leap_year();
+
+    same_day || leap_day_fallback
+}
+
+/// Draw a random message with `draw` and fetch it from Discord.
+///
+/// Messages that were deleted without the index noticing, or that no longer
+/// pass the guild's filters, are removed from the index and another message is
+/// drawn, up to the configured number of attempts. Returns `None` if there is
+/// nothing left to draw.
+pub async fn draw_message(
+    guild_id: GuildId,
+    data: &Data,
+    http: impl AsRef<Http>,
+    draw: impl Fn(&Index) -> Option<(ChannelId, MessageId)>,
+) -> Result<Option<Message>> {
+    let http = http.as_ref();
+    let attempts = data.config.index.draw_attempts.max(1);
+    let filters = &data.guild_config(guild_id).await.filters;
+
+    for attempt in 1..=attempts {
+        let Some((channel_id, message_id)) = draw(&*data.index.lock().await) else {
+            return Ok(None);
+        };
+        trace!(?channel_id, ?message_id, attempt, "drew message");
+
+        match http.get_message(channel_id, message_id).await {
+            Ok(mut message) => {
+                // messages fetched over HTTP don't include their guild, which
+                // links to the message need
+                message.guild_id = Some(guild_id);
+
+                if filters.allows(&message, Utc::now()) {
+                    // messages indexed before their metadata was recorded get
+                    // it now, so anything drawing by it sees them next time
+                    data.index
+                        .lock()
+                        .await
+                        .refresh_message(guild_id, &message, filters)
+                        .await?;
+
+                    return Ok(Some(message));
+                }
+
+                // the message was edited or the filters changed since it was
+                // indexed
+                debug!(
+                    ?channel_id,
+                    ?message_id,
+                    "drawn message is no longer eligible, removing it from the index"
+                );
+
+                data.index
+                    .lock()
+                    .await
+                    .remove_message(guild_id, channel_id, message_id)
+                    .await?;
+            }
+            Err(err) if utils::is_not_found(&err) => {
+                warn!(
+                    ?channel_id,
+                    ?message_id,
+                    "drawn message no longer exists, removing it from the index"
+                );
+
+                data.index
+                    .lock()
+                    .await
+                    .remove_message(guild_id, channel_id, message_id)
+                    .await?;
+            }
+            Err(err) => return Err(err).wrap_err("failed to get drawn message"),
+        }
+    }
+
+    bail!(
+        "gave up after {} attempts because every drawn message had been deleted or was ineligible",
+        attempts
+    );
+}
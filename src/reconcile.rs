@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use color_eyre::eyre::{Result, WrapErr};
+use serenity::all::{ChannelId, GetMessages, GuildId, Http};
+
+use crate::{
+    data::{
+        index::{Index, IndexedMessage, ReconcileReport},
+        Data,
+    },
+    filter::Filters,
+    ownership::Ownership,
+};
+
+/// Periodically reconcile the index of every guild with Discord.
+///
+/// The startup index only walks forward from the last indexed message, so it
+/// never notices messages that were deleted while the bot was offline. This
+/// task catches those.
+pub async fn reconcile_task(data: Data, http: Arc<Http>) {
+    let interval = data.config.index.reconcile_interval_hours;
+
+    if interval == 0 {
+        debug!("index reconciliation is disabled");
+        return;
+    }
+
+    debug!("starting index reconciliation task");
+
+    loop {
+        // the startup index just ran, so there's no point in reconciling right
+        // away
+        tokio::time::sleep(Duration::hours(interval as i64).to_std().unwrap()).await;
+
+        let guilds = data.state.lock().await.get_guilds();
+
+        for guild_id in guilds {
+            let channels = data.state.lock().await.get_channels(guild_id);
+
+            match reconcile_guild(&data, &http, guild_id, channels).await {
+                Ok(report) if report.is_empty() => {
+                    debug!(?guild_id, "index reconciliation found no drift");
+                }
+                Ok(report) => info!(?guild_id, "index reconciliation {}", report),
+                Err(err) => error!(?guild_id, "failed to reconcile index: {:?}", err),
+            }
+        }
+    }
+}
+
+/// Reconcile the index of the given channels with Discord.
+pub async fn reconcile_guild(
+    data: &Data,
+    http: &Http,
+    guild_id: GuildId,
+    channels: Vec<ChannelId>,
+) -> Result<ReconcileReport> {
+    let mut report = ReconcileReport::default();
+    let guild_config = data.guild_config(guild_id).await;
+    let filters = &guild_config.filters;
+    let ownership = data
+        .state
+        .lock()
+        .await
+        .ownership(guild_id, &guild_config.monologues);
+
+    for channel_id in channels {
+        debug!(?channel_id, "reconciling channel");
+
+        let fetched_at = Utc::now();
+
+        // fetching can take a while, so the index is only locked once we know
+        // what the channel looks like
+        let fetched = fetch_channel(http, channel_id, filters, &ownership)
+            .await
+            .wrap_err_with(|| format!("failed to fetch messages in {}", channel_id))?;
+
+        report += data
+            .index
+            .lock()
+            .await
+            .reconcile_channel(guild_id, channel_id, fetched, fetched_at)
+            .await?;
+    }
+
+    Ok(report)
+}
+
+/// Fetch every message in a channel that passes the filters and isn't a
+/// foreign post, newest first.
+async fn fetch_channel(
+    http: &Http,
+    channel_id: ChannelId,
+    filters: &Filters,
+    ownership: &Ownership,
+) -> Result<Vec<IndexedMessage>> {
+    let mut fetched = Vec::new();
+    let mut before = None;
+
+    loop {
+        let request = match before {
+            Some(before) => GetMessages::new().before(before),
+            None => GetMessages::new(),
+        }
+        .limit(100);
+
+        let messages = channel_id.messages(http, request).await?;
+
+        let Some(last) = messages.last() else {
+            break;
+        };
+        before = Some(last.id);
+
+        fetched.extend(
+            messages
+                .iter()
+                .filter(|msg| Index::is_message_valid(msg, filters) && !ownership.is_foreign(msg))
+                .map(IndexedMessage::from),
+        );
+    }
+
+    Ok(fetched)
+}
@@ -0,0 +1,569 @@
+//! The template language used for random draw messages.
+//!
+//! Templates are plain text with placeholders in braces:
+//! - `{variable}` is replaced with the value of the variable
+//! - `{a|b|"text"}` uses the first of the alternatives that isn't empty, where
+//!   quoted alternatives are literal text
+//! - `{#if variable}...{#else}...{/if}` only includes its contents if the
+//!   variable isn't empty, or the optional `{#else}` branch if it is. The
+//!   condition can be negated as `{#if !variable}`
+//! - `{{` and `}}` are literal braces
+//!
+//! Literal alternatives can't contain `|`, `"` or braces.
+//!
+//! A variable is empty if it has no value, e.g. `{channel.name}` when the
+//! channel couldn't be fetched, or if it is a count of zero.
+//!
+//! Templates are parsed when the config is loaded, so unknown variables and
+//! syntax errors are reported before they can end up in a draw. Templates
+//! that are only previewed can be parsed with [`Template::parse_lenient`] to
+//! list every unknown variable instead of stopping at the first.
+
+use std::{fmt, str::FromStr};
+
+use chrono::Utc;
+use chrono_tz::Tz;
+use color_eyre::eyre::{bail, eyre, Result};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use serenity::all::{Mentionable, Message};
+
+use crate::utils;
+
+/// A parsed template. Keeps its source so it can be shown and saved as it was
+/// written.
+#[derive(Debug, Clone, SerializeDisplay, DeserializeFromStr)]
+pub struct Template {
+    source: String,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    /// The first non-empty alternative
+    Value(Vec<Alternative>),
+    If {
+        negate: bool,
+        /// Only ever a variable or an unknown variable
+        condition: Alternative,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Alternative {
+    Variable(Variable),
+    Literal(String),
+    /// A variable that doesn't exist, only allowed by
+    /// [`Template::parse_lenient`]. Always empty
+    Unknown(String),
+}
+
+/// A variable that can be used in templates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variable {
+    /// A mention of the message author
+    Author,
+    /// The name of the message author
+    AuthorName,
+    /// The ID of the message author
+    AuthorId,
+    /// A mention of the author's monologue channel
+    Channel,
+    /// The ID of the author's monologue channel
+    ChannelId,
+    /// The name of the author's monologue channel
+    ChannelName,
+    /// The name of the server
+    GuildName,
+    /// The timestamp of the message in one of Discord's timestamp styles
+    Timestamp(char),
+    /// How long ago the message was posted, e.g. `3 months`
+    Age,
+    /// The number of full days since the message was posted
+    AgeDays,
+    /// The number of full years since the message was posted
+    YearsAgo,
+    /// The number of attachments on the message
+    Attachments,
+    /// The total number of reactions on the message
+    Reactions,
+    /// How many messages the random draw has drawn, including this one
+    DrawNumber,
+}
+
+/// The valid styles of `{timestamp:<style>}`, see
+/// https://discord.com/developers/docs/reference#message-formatting-timestamp-styles
+const TIMESTAMP_STYLES: &str = "tTdDfFR";
+
+impl FromStr for Variable {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+
+        if let Some(style) = s.strip_prefix("timestamp:") {
+            let mut chars = style.chars();
+
+            return match (chars.next(), chars.next()) {
+                (Some(style), None) if TIMESTAMP_STYLES.contains(style) => {
+                    Ok(Variable::Timestamp(style))
+                }
+                _ => Err(eyre!(
+                    "invalid timestamp style `{}`, must be one of `t`, `T`, `d`, `D`, `f`, `F` or `R`",
+                    style
+                )),
+            };
+        }
+
+        match s {
+            "author" => Ok(Variable::Author),
+            "author.name" => Ok(Variable::AuthorName),
+            "author.id" => Ok(Variable::AuthorId),
+            "channel" => Ok(Variable::Channel),
+            "channel.id" => Ok(Variable::ChannelId),
+            "channel.name" => Ok(Variable::ChannelName),
+            "guild.name" => Ok(Variable::GuildName),
+            "timestamp" => Err(eyre!("`timestamp` needs a style, e.g. `{{timestamp:f}}`")),
+            "age" => Ok(Variable::Age),
+            "age.days" => Ok(Variable::AgeDays),
+            "years_ago" => Ok(Variable::YearsAgo),
+            "attachments" => Ok(Variable::Attachments),
+            "reactions" => Ok(Variable::Reactions),
+            "draw.number" => Ok(Variable::DrawNumber),
+            _ => Err(eyre!("unknown variable `{}`", s)),
+        }
+    }
+}
+
+/// Everything a template can refer to when it is rendered.
+///
+/// Values that require additional API calls are optional and only need to be
+/// looked up if the template uses them, see [`Template::uses`].
+pub struct TemplateContext<'a> {
+    pub message: &'a Message,
+    /// The timezone used for dates
+    pub tz: Tz,
+    pub channel_name: Option<String>,
+    pub guild_name: Option<String>,
+    pub draw_number: Option<u64>,
+}
+
+enum Value {
+    Text(String),
+    Count(u64),
+    Missing,
+}
+
+impl Value {
+    fn is_empty(&self) -> bool {
+        match self {
+            Value::Text(text) => text.is_empty(),
+            Value::Count(count) => *count == 0,
+            Value::Missing => true,
+        }
+    }
+
+    fn into_string(self) -> String {
+        match self {
+            Value::Text(text) => text,
+            Value::Count(count) => count.to_string(),
+            Value::Missing => String::new(),
+        }
+    }
+}
+
+impl TemplateContext<'_> {
+    fn alternative(&self, alternative: &Alternative) -> Value {
+        match alternative {
+            Alternative::Variable(variable) => self.value(*variable),
+            Alternative::Literal(text) => Value::Text(text.clone()),
+            Alternative::Unknown(_) => Value::Missing,
+        }
+    }
+
+    fn value(&self, variable: Variable) -> Value {
+        let message = self.message;
+
+        match variable {
+            Variable::Author => Value::Text(message.author.mention().to_string()),
+            Variable::AuthorName => Value::Text(message.author.name.clone()),
+            Variable::AuthorId => Value::Text(message.author.id.to_string()),
+            Variable::Channel => Value::Text(message.channel_id.mention().to_string()),
+            Variable::ChannelId => Value::Text(message.channel_id.to_string()),
+            Variable::ChannelName => self
+                .channel_name
+                .clone()
+                .map_or(Value::Missing, Value::Text),
+            Variable::GuildName => self.guild_name.clone().map_or(Value::Missing, Value::Text),
+            Variable::Timestamp(style) => Value::Text(utils::generate_discord_timestamp(
+                *message.timestamp,
+                &style.to_string(),
+            )),
+            Variable::Age => Value::Text(format_age(message, self.tz)),
+            Variable::AgeDays => {
+                Value::Count((Utc::now() - *message.timestamp).num_days().max(0) as u64)
+            }
+            Variable::YearsAgo => Value::Count(utils::years_ago(message, self.tz) as u64),
+            Variable::Attachments => Value::Count(message.attachments.len() as u64),
+            Variable::Reactions => Value::Count(
+                message
+                    .reactions
+                    .iter()
+                    .map(|reaction| reaction.count)
+                    .sum(),
+            ),
+            Variable::DrawNumber => self.draw_number.map_or(Value::Missing, Value::Count),
+        }
+    }
+}
+
+/// Describe how long ago a message was posted in its largest whole unit
+fn format_age(message: &Message, tz: Tz) -> String {
+    let plural = |n: i64, unit: &str| format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" });
+
+    let years = utils::years_ago(message, tz);
+    if years > 0 {
+        return plural(years as i64, "year");
+    }
+
+    let age = Utc::now() - *message.timestamp;
+
+    match age.num_days() {
+        days if days >= 30 => plural(days / 30, "month"),
+        days if days >= 7 => plural(days / 7, "week"),
+        days if days >= 1 => plural(days, "day"),
+        _ if age.num_hours() >= 1 => plural(age.num_hours(), "hour"),
+        _ => "less than an hour".to_string(),
+    }
+}
+
+impl Template {
+    /// Parse a template like [`Template::from_str`], but allow unknown
+    /// variables instead of failing. They render as empty and are returned so
+    /// they can be reported, e.g. when previewing a template.
+    pub fn parse_lenient(source: &str) -> Result<(Self, Vec<String>)> {
+        let mut unknown = Vec::new();
+        let nodes = parse(source, Some(&mut unknown))?;
+
+        let template = Template {
+            source: source.to_string(),
+            nodes,
+        };
+
+        Ok((template, unknown))
+    }
+
+    /// Render the template for the given context
+    pub fn render(&self, cx: &TemplateContext<'_>) -> String {
+        let mut out = String::new();
+        render_nodes(&self.nodes, cx, &mut out);
+        out
+    }
+
+    /// Check if the template refers to a variable anywhere
+    pub fn uses(&self, variable: Variable) -> bool {
+        nodes_use(&self.nodes, variable)
+    }
+}
+
+fn render_nodes(nodes: &[Node], cx: &TemplateContext<'_>, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Value(alternatives) => {
+                let value = alternatives
+                    .iter()
+                    .map(|alternative| cx.alternative(alternative))
+                    .find(|value| !value.is_empty());
+
+                if let Some(value) = value {
+                    out.push_str(&value.into_string());
+                }
+            }
+            Node::If {
+                negate,
+                condition,
+                then,
+                otherwise,
+            } => {
+                if cx.alternative(condition).is_empty() == *negate {
+                    render_nodes(then, cx, out);
+                } else {
+                    render_nodes(otherwise, cx, out);
+                }
+            }
+        }
+    }
+}
+
+fn nodes_use(nodes: &[Node], variable: Variable) -> bool {
+    nodes.iter().any(|node| match node {
+        Node::Text(_) => false,
+        Node::Value(alternatives) => alternatives.contains(&Alternative::Variable(variable)),
+        Node::If {
+            condition,
+            then,
+            otherwise,
+            ..
+        } => {
+            *condition == Alternative::Variable(variable)
+                || nodes_use(then, variable)
+                || nodes_use(otherwise, variable)
+        }
+    })
+}
+
+/// A block that was opened by `{#if}` and not closed yet
+struct OpenIf {
+    negate: bool,
+    condition: Alternative,
+    then: Vec<Node>,
+    otherwise: Vec<Node>,
+    in_else: bool,
+}
+
+impl FromStr for Template {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(source: &str) -> Result<Self> {
+        let nodes =
+            parse(source, None).map_err(|err| eyre!("invalid template `{}`: {}", source, err))?;
+
+        Ok(Template {
+            source: source.to_string(),
+            nodes,
+        })
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Parse the nodes of a template. Unknown variables are an error, unless
+/// `unknown` is given to collect them in.
+fn parse(source: &str, mut unknown: Option<&mut Vec<String>>) -> Result<Vec<Node>> {
+    // the innermost open block is last. nodes outside of any block go
+    // straight into `nodes`
+    let mut stack: Vec<OpenIf> = Vec::new();
+    let mut nodes = Vec::new();
+    let mut text = String::new();
+    let mut chars = source.char_indices().peekable();
+
+    let push = |stack: &mut Vec<OpenIf>, nodes: &mut Vec<Node>, node: Node| match stack.last_mut() {
+        Some(open) if open.in_else => open.otherwise.push(node),
+        Some(open) => open.then.push(node),
+        None => nodes.push(node),
+    };
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '{' if chars.peek().is_some_and(|&(_, c)| c == '{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek().is_some_and(|&(_, c)| c == '}') => {
+                chars.next();
+                text.push('}');
+            }
+            '}' => {
+                bail!(
+                    "unexpected `}}` at position {}, use `}}}}` for a literal brace",
+                    start
+                );
+            }
+            '{' => {
+                let Some(len) = source[start + 1..].find('}') else {
+                    bail!(
+                        "unclosed `{{` at position {}, use `{{{{` for a literal brace",
+                        start
+                    );
+                };
+                let end = start + 1 + len;
+                let inner = source[start + 1..end].trim();
+
+                // skip over the rest of the placeholder
+                while chars.next_if(|&(i, _)| i <= end).is_some() {}
+
+                if !text.is_empty() {
+                    push(
+                        &mut stack,
+                        &mut nodes,
+                        Node::Text(std::mem::take(&mut text)),
+                    );
+                }
+
+                if let Some(condition) = inner.strip_prefix("#if ") {
+                    let condition = condition.trim();
+                    let (negate, variable) = match condition.strip_prefix('!') {
+                        Some(variable) => (true, variable),
+                        None => (false, condition),
+                    };
+
+                    stack.push(OpenIf {
+                        negate,
+                        condition: parse_variable(variable, &mut unknown)?,
+                        then: Vec::new(),
+                        otherwise: Vec::new(),
+                        in_else: false,
+                    });
+                } else if inner == "#else" {
+                    match stack.last_mut() {
+                        Some(open) if !open.in_else => open.in_else = true,
+                        Some(_) => {
+                            bail!("`{{#else}}` at position {} is repeated", start);
+                        }
+                        None => {
+                            bail!(
+                                "`{{#else}}` at position {} is outside of an `{{#if}}`",
+                                start
+                            );
+                        }
+                    }
+                } else if inner == "/if" {
+                    let Some(open) = stack.pop() else {
+                        bail!("`{{/if}}` at position {} has no matching `{{#if}}`", start);
+                    };
+                    let node = Node::If {
+                        negate: open.negate,
+                        condition: open.condition,
+                        then: open.then,
+                        otherwise: open.otherwise,
+                    };
+
+                    push(&mut stack, &mut nodes, node);
+                } else {
+                    push(
+                        &mut stack,
+                        &mut nodes,
+                        Node::Value(parse_alternatives(inner, &mut unknown)?),
+                    );
+                }
+            }
+            c => text.push(c),
+        }
+    }
+
+    if !stack.is_empty() {
+        bail!("`{{#if}}` is never closed with `{{/if}}`");
+    }
+
+    if !text.is_empty() {
+        nodes.push(Node::Text(text));
+    }
+
+    Ok(nodes)
+}
+
+fn parse_alternatives(
+    inner: &str,
+    unknown: &mut Option<&mut Vec<String>>,
+) -> Result<Vec<Alternative>> {
+    inner
+        .split('|')
+        .map(|alternative| {
+            let alternative = alternative.trim();
+
+            match alternative
+                .strip_prefix('"')
+                .and_then(|literal| literal.strip_suffix('"'))
+            {
+                Some(literal) => Ok(Alternative::Literal(literal.to_string())),
+                None => parse_variable(alternative, unknown),
+            }
+        })
+        .collect()
+}
+
+fn parse_variable(name: &str, unknown: &mut Option<&mut Vec<String>>) -> Result<Alternative> {
+    match (name.parse(), unknown) {
+        (Ok(variable), _) => Ok(Alternative::Variable(variable)),
+        (Err(_), Some(unknown)) => {
+            unknown.push(name.trim().to_string());
+            Ok(Alternative::Unknown(name.trim().to_string()))
+        }
+        (Err(err), None) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Render `source` for a message without attachments, with the given
+    /// channel and guild names
+    fn render(source: &str, channel_name: Option<&str>, guild_name: Option<&str>) -> String {
+        let template = source.parse::<Template>().unwrap();
+        let message = Message::default();
+        let cx = TemplateContext {
+            message: &message,
+            tz: Tz::UTC,
+            channel_name: channel_name.map(str::to_string),
+            guild_name: guild_name.map(str::to_string),
+            draw_number: None,
+        };
+
+        template.render(&cx)
+    }
+
+    #[test]
+    fn nested_conditionals_pick_their_own_branches() {
+        let source = "{#if channel.name}in {channel.name}{#if !guild.name}, somewhere{#else} on {guild.name}{/if}{#else}nowhere{/if}";
+
+        assert_eq!(
+            render(source, Some("diary"), Some("server")),
+            "in diary on server"
+        );
+        assert_eq!(render(source, Some("diary"), None), "in diary, somewhere");
+        assert_eq!(render(source, None, Some("server")), "nowhere");
+    }
+
+    #[test]
+    fn escaped_braces_next_to_placeholders_stay_literal() {
+        assert_eq!(render("{{{channel.name}}}", Some("diary"), None), "{diary}");
+        assert_eq!(
+            render("{{channel.name}} is {channel.name}", Some("diary"), None),
+            "{channel.name} is diary"
+        );
+    }
+
+    #[test]
+    fn empty_values_fall_back_to_the_next_alternative() {
+        let source = r#"{channel.name|guild.name|"somewhere"}"#;
+
+        assert_eq!(render(source, Some("diary"), Some("server")), "diary");
+        assert_eq!(render(source, None, Some("server")), "server");
+        assert_eq!(render(source, Some(""), None), "somewhere");
+        // a count of zero is empty too
+        assert_eq!(
+            render(r#"{attachments|"no"} files"#, None, None),
+            "no files"
+        );
+    }
+
+    #[test]
+    fn unclosed_blocks_and_placeholders_are_errors() {
+        assert!("{#if channel.name}text".parse::<Template>().is_err());
+        assert!("{#if channel.name}{#else}{#else}{/if}"
+            .parse::<Template>()
+            .is_err());
+        assert!("{/if}".parse::<Template>().is_err());
+        assert!("text {channel.name".parse::<Template>().is_err());
+        assert!("text }".parse::<Template>().is_err());
+    }
+
+    #[test]
+    fn unknown_variables_are_errors_unless_parsed_leniently() {
+        assert!("{channel.nam}".parse::<Template>().is_err());
+        assert!("{#if !nope}x{/if}".parse::<Template>().is_err());
+
+        let (template, unknown) =
+            Template::parse_lenient(r#"{nope|"fallback"} {#if also_nope}x{/if}"#).unwrap();
+        assert_eq!(unknown, ["nope", "also_nope"]);
+        assert!(!template.uses(Variable::ChannelName));
+    }
+}
@@ -1,10 +1,25 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use color_eyre::eyre::{bail, Result, WrapErr};
-use lazy_regex::regex_replace_all;
-use serenity::all::{Channel, ChannelId, Context, EditChannel, Mentionable, Message};
+use poise::CreateReply;
+use serenity::all::{
+    AttachmentId, Channel, ChannelId, Context, CreateAttachment, CreateEmbed, CreateEmbedAuthor,
+    CreateMessage, EditChannel, GuildId, Http, Message, StatusCode,
+};
 use tokio::sync::MutexGuard;
 
-use crate::data::{state::State, Data};
+use crate::data::{config::RepostFormat, state::State, Data};
+
+/// The maximum number of embeds Discord allows in a single message
+const MAX_EMBEDS: usize = 10;
+
+/// The maximum length of an embed field value
+const MAX_EMBED_FIELD_LEN: usize = 1024;
+
+/// The maximum length of the content of a message
+const MAX_CONTENT_LEN: usize = 2000;
 
 /// Generates a Discord timestamp string from the provided timestamp and format.
 ///
@@ -18,57 +33,143 @@ use crate::data::{state::State, Data};
 /// - `f`: Short date and time format (e.g. 20 April 2021 16:20)
 /// - `F`: Long date and time format (e.g. Tuesday, 20 April 2021 16:20)
 /// - `R`: Relative time format (e.g. 2 months ago)
-fn generate_discord_timestamp(timestamp: DateTime<Utc>, format: &str) -> String {
+pub fn generate_discord_timestamp(timestamp: DateTime<Utc>, format: &str) -> String {
     format!("<t:{}:{}>", timestamp.timestamp(), format)
 }
 
-/// Apply our custom formatting to the prefix. The following replacements are
-/// made:
-/// - `{author}`: A mention of the message author
-/// - `{author.name}`: The name of the message author
-/// - `{author.id}`: The ID of the message author
-/// - `{channel}`: A mention of the author's monologue channel
-/// - `{channel.id}`: The ID of the author's monologue channel
-/// - `{timestamp:<format>}`: The timestamp of the message with the specified
-///   format
+/// The number of full years since the message was posted, using calendar dates
+/// in the given timezone
+pub fn years_ago(message: &Message, tz: Tz) -> u32 {
+    let posted = message.timestamp.with_timezone(&tz).date_naive();
+    let today = Utc::now().with_timezone(&tz).date_naive();
+
+    today.years_since(posted).unwrap_or_default()
+}
+
+/// A repost of a message, ready to be sent as a new message or as a reply to a
+/// command.
+pub struct Repost {
+    pub content: String,
+    pub embeds: Vec<CreateEmbed>,
+    /// Attachments of the original message that are uploaded again
+    pub files: Vec<CreateAttachment>,
+}
+
+impl From<Repost> for CreateMessage {
+    fn from(repost: Repost) -> Self {
+        let message = CreateMessage::new()
+            .embeds(repost.embeds)
+            .add_files(repost.files);
+
+        // an embed repost without a prefix has no content
+        if repost.content.is_empty() {
+            message
+        } else {
+            message.content(repost.content)
+        }
+    }
+}
+
+impl From<Repost> for CreateReply {
+    fn from(repost: Repost) -> Self {
+        CreateReply {
+            content: Some(repost.content).filter(|content| !content.is_empty()),
+            embeds: repost.embeds,
+            attachments: repost.files,
+            ..Default::default()
+        }
+    }
+}
+
+/// Download the attachments of a message so they can be uploaded again with
+/// the repost, keyed by the ID of the original attachment.
 ///
-/// There is currently no `{channel.name}` replacement because that requires an
-/// additional API call
-fn format_prefix(mut prefix: String, message: &Message) -> String {
-    let channel_id = &message.channel_id;
-    let author = &message.author;
+/// Attachments are downloaded in order until their combined size would exceed
+/// `limit_bytes`. Attachments that don't fit or fail to download are left out
+/// and linked in the repost instead.
+pub async fn download_attachments(
+    message: &Message,
+    limit_bytes: u64,
+) -> HashMap<AttachmentId, CreateAttachment> {
+    let mut uploads = HashMap::new();
+    let mut total = 0;
 
-    prefix = prefix
-        .replace("{author}", &author.mention().to_string())
-        .replace("{author.name}", &author.name)
-        .replace("{author.id}", &author.id.to_string())
-        .replace("{channel}", &channel_id.mention().to_string())
-        .replace("{channel.id}", &channel_id.to_string());
-
-    prefix = regex_replace_all!(
-        r#"\{timestamp:(?P<format>[tTdDfFR])\}"#,
-        &prefix,
-        |_, format| { generate_discord_timestamp(*message.timestamp, format) }
-    )
-    .to_string();
+    for attachment in &message.attachments {
+        if total + attachment.size as u64 > limit_bytes {
+            trace!(
+                filename = attachment.filename,
+                size = attachment.size,
+                "attachment exceeds the upload limit, linking it instead"
+            );
+            continue;
+        }
+
+        match attachment.download().await {
+            Ok(data) => {
+                total += attachment.size as u64;
+                uploads.insert(
+                    attachment.id,
+                    CreateAttachment::bytes(data, &attachment.filename),
+                );
+            }
+            Err(err) => warn!(
+                ?err,
+                filename = attachment.filename,
+                "failed to download attachment, linking it instead"
+            ),
+        }
+    }
 
-    prefix
+    uploads
+}
+
+/// Generate a repost of the message in the given format.
+///
+/// If a prefix is provided, it is placed above the repost. Attachments in
+/// `uploads` are uploaded with the repost, the rest are linked.
+pub fn format_repost(
+    message: Message,
+    prefix: Option<String>,
+    format: RepostFormat,
+    mut uploads: HashMap<AttachmentId, CreateAttachment>,
+) -> Repost {
+    let (content, embeds) = match format {
+        RepostFormat::Text => (
+            format_repost_content(&message, prefix, &uploads),
+            Vec::new(),
+        ),
+        RepostFormat::Embed => format_repost_embed(&message, prefix, &uploads),
+    };
+
+    // keep the files in the order of the original attachments
+    let files = message
+        .attachments
+        .iter()
+        .filter_map(|attachment| uploads.remove(&attachment.id))
+        .collect();
+
+    Repost {
+        content,
+        embeds,
+        files,
+    }
 }
 
 /// Generate the `content` field for a repost.
 ///
-/// The bulk of the contents is the `message.content` with any attachment URLs
-/// appended to the end of the message content.
+/// The bulk of the contents is the `message.content` with the URLs of any
+/// attachments that aren't in `uploads` appended to the end of the message
+/// content.
 ///
 /// If a prefix is provided, the entire message content is prepended with the
 /// prefix and two newlines are inserted between the prefix and the message
 /// content.
-pub fn format_repost_content(message: Message, prefix: Option<&str>) -> String {
-    let prefix = prefix
-        .map(|p| format_prefix(p.to_string(), &message))
-        .unwrap_or_default();
-
-    let mut content = prefix;
+pub fn format_repost_content(
+    message: &Message,
+    prefix: Option<String>,
+    uploads: &HashMap<AttachmentId, CreateAttachment>,
+) -> String {
+    let mut content = prefix.unwrap_or_default();
 
     if !message.content.is_empty() {
         content.push_str(&format!(
@@ -78,7 +179,11 @@ pub fn format_repost_content(message: Message, prefix: Option<&str>) -> String {
         ));
     }
 
-    for attachment in message.attachments {
+    for attachment in &message.attachments {
+        if uploads.contains_key(&attachment.id) {
+            continue;
+        }
+
         content.push_str(&format!(
             "{}{}",
             if !content.is_empty() { "\n" } else { "" },
@@ -89,14 +194,150 @@ pub fn format_repost_content(message: Message, prefix: Option<&str>) -> String {
     content
 }
 
+/// Generate the content and embeds of an embed based repost.
+///
+/// The prefix becomes the message content and the message itself is shown in
+/// an embed with its author, timestamp and a link back to the original. The
+/// first image attachment is shown inline and the rest are listed in a field,
+/// except for the ones in `uploads` which are attached to the repost. Any
+/// embeds of the original message are included after the repost embed.
+fn format_repost_embed(
+    message: &Message,
+    prefix: Option<String>,
+    uploads: &HashMap<AttachmentId, CreateAttachment>,
+) -> (String, Vec<CreateEmbed>) {
+    let content = prefix.unwrap_or_default();
+
+    let author = &message.author;
+    let author_name = author.global_name.as_deref().unwrap_or(&author.name);
+
+    let mut embed = CreateEmbed::new()
+        .author(CreateEmbedAuthor::new(author_name).icon_url(author.face()))
+        .title("Jump to message")
+        .url(message.link())
+        .timestamp(message.timestamp);
+
+    if !message.content.is_empty() {
+        embed = embed.description(&message.content);
+    }
+
+    let image = message.attachments.iter().position(|attachment| {
+        attachment
+            .content_type
+            .as_deref()
+            .is_some_and(|content_type| content_type.starts_with("image/"))
+    });
+
+    if let Some(image) = image {
+        let attachment = &message.attachments[image];
+
+        // uploaded images are referenced by their filename, which also keeps
+        // Discord from showing them a second time above the embed
+        embed = match uploads.get(&attachment.id) {
+            Some(file) => embed.attachment(&file.filename),
+            None => embed.image(&attachment.url),
+        };
+    }
+
+    let mut attachments = String::new();
+
+    for (i, attachment) in message.attachments.iter().enumerate() {
+        if Some(i) == image || uploads.contains_key(&attachment.id) {
+            continue;
+        }
+
+        let line = format!("[{}]({})\n", attachment.filename, attachment.url);
+
+        if attachments.len() + line.len() > MAX_EMBED_FIELD_LEN {
+            break;
+        }
+
+        attachments.push_str(&line);
+    }
+
+    if !attachments.is_empty() {
+        embed = embed.field("Attachments", attachments.trim_end(), false);
+    }
+
+    let mut embeds = vec![embed];
+
+    embeds.extend(
+        message
+            .embeds
+            .iter()
+            .take(MAX_EMBEDS - 1)
+            .cloned()
+            .map(CreateEmbed::from),
+    );
+
+    (content, embeds)
+}
+
+/// Join lines into as few message contents as possible without exceeding
+/// Discord's length limit. Lines are never split across contents, but lines
+/// that are too long on their own are cut short.
+pub fn split_content(lines: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut contents = Vec::new();
+    let mut content = String::new();
+
+    for mut line in lines {
+        if line.chars().count() > MAX_CONTENT_LEN {
+            line = line.chars().take(MAX_CONTENT_LEN - 1).collect();
+            line.push('…');
+        }
+
+        if !content.is_empty()
+            && content.chars().count() + 1 + line.chars().count() > MAX_CONTENT_LEN
+        {
+            contents.push(std::mem::take(&mut content));
+        }
+
+        if !content.is_empty() {
+            content.push('\n');
+        }
+
+        content.push_str(&line);
+    }
+
+    if !content.is_empty() {
+        contents.push(content);
+    }
+
+    contents
+}
+
+/// Check if a request failed because what it asked for doesn't exist
+pub fn is_not_found(err: &serenity::Error) -> bool {
+    matches!(
+        err,
+        serenity::Error::Http(serenity::all::HttpError::UnsuccessfulRequest(res))
+            if res.status_code == StatusCode::NOT_FOUND
+    )
+}
+
+/// Look up the guild that the provided channel belongs to.
+pub async fn guild_of(http: impl AsRef<Http>, channel_id: ChannelId) -> Result<GuildId> {
+    let Channel::Guild(channel) = http
+        .as_ref()
+        .get_channel(channel_id)
+        .await
+        .wrap_err("failed to get channel")?
+    else {
+        bail!("provided channel is not a guild channel");
+    };
+
+    Ok(channel.guild_id)
+}
+
 /// A wrapper around `State::next_position` that ensures the next position is
 /// valid.
 pub async fn checked_next_position(
     cx: &Context,
+    guild_id: GuildId,
     existing: Option<u16>,
     state: &mut MutexGuard<'_, State>,
 ) -> Result<u16> {
-    let mut next_pos = state.next_position();
+    let mut next_pos = state.next_position(guild_id);
     trace!("next position: {}", next_pos);
 
     // if an existing position was provided and it is already the topmost
@@ -108,9 +349,9 @@ pub async fn checked_next_position(
 
     if next_pos == 0 {
         trace!("next position is zero, resetting channel positions");
-        initialize_channel_positions(cx, state).await?;
+        initialize_channel_positions(cx, guild_id, state).await?;
 
-        next_pos = state.next_position();
+        next_pos = state.next_position(guild_id);
         trace!("new next position: {}", next_pos);
     }
 
@@ -136,18 +377,21 @@ pub async fn move_channel_to_top(cx: &Context, data: &Data, channel_id: ChannelI
 
     let mut state = data.state.lock().await;
 
-    let next_pos = checked_next_position(cx, Some(channel.position), &mut state).await?;
+    let next_pos =
+        checked_next_position(cx, channel.guild_id, Some(channel.position), &mut state).await?;
 
     channel
         .edit(cx, EditChannel::new().position(next_pos))
         .await?;
 
-    state.set_channel_position(channel.id, next_pos).await?;
+    state
+        .set_channel_position(channel.guild_id, channel.id, next_pos)
+        .await?;
 
     Ok(())
 }
 
-/// Initialize the positions of all channels in the provided category.
+/// Initialize the positions of all monologue channels in the provided guild.
 ///
 /// This moves all channels to `u16::MAX - N` where N is the current channel
 /// position relative to the others. This provides a clean slate for ordering
@@ -166,9 +410,10 @@ pub async fn move_channel_to_top(cx: &Context, data: &Data, channel_id: ChannelI
 /// - Channel 3: u16::MAX
 async fn initialize_channel_positions(
     cx: &Context,
+    guild_id: GuildId,
     state: &mut MutexGuard<'_, State>,
 ) -> Result<()> {
-    let channel_ids = state.get_channels();
+    let channel_ids = state.get_channels(guild_id);
     trace!(
         "initializing channel positions for {} channels",
         channel_ids.len()
@@ -205,7 +450,9 @@ async fn initialize_channel_positions(
             .edit(&cx.http, EditChannel::new().position(new_pos))
             .await?;
 
-        state.set_channel_position(channel.id, new_pos).await?;
+        state
+            .set_channel_position(guild_id, channel.id, new_pos)
+            .await?;
     }
 
     Ok(())